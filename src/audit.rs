@@ -0,0 +1,345 @@
+//! Auditing which signing keys apt trusts for each source.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+
+use crate::keyring::{installed_keyrings, InstalledKeyring, KeyringLocations};
+use crate::lists::SignerFinder;
+use crate::option::{KnownOptionName, OptionValue};
+use crate::pgp::{KeyEncoding, KeyInfo, PgpClient};
+use crate::sources::{installed_entries, InstalledEntry, SignedBy};
+
+/// Options which weaken or disable signature checks for a source when they're enabled.
+const INSECURE_OPTIONS: [KnownOptionName; 4] = [
+    KnownOptionName::Trusted,
+    KnownOptionName::AllowInsecure,
+    KnownOptionName::AllowWeak,
+    KnownOptionName::AllowDowngradeToInsecure,
+];
+
+/// A problem found by an audit.
+#[derive(Debug, Clone)]
+pub enum Finding {
+    /// A key in a keyring that apt trusts for every source.
+    GloballyTrustedKey { keyring: PathBuf, key: KeyInfo },
+
+    /// A source entry without a `Signed-By` option, which trusts every globally trusted key.
+    MissingSignedBy { entry: String },
+
+    /// A source entry with an option that weakens or disables signature checks.
+    InsecureOption {
+        entry: String,
+        option: KnownOptionName,
+    },
+
+    /// A key in a keyring which doesn't sign any of the sources that use the keyring.
+    ExtraKey { keyring: PathBuf, key: KeyInfo },
+}
+
+/// The headings of each section of an audit report, in order.
+const SECTION_HEADINGS: [&str; 4] = [
+    "These keys are trusted to sign every source:",
+    "These sources don't have a `Signed-By` option, so they trust every globally trusted key:",
+    "These sources weaken or disable signature checks:",
+    "These keys don't sign any of the sources that trust them:",
+];
+
+impl Finding {
+    /// The index of the section of the audit report this finding goes in.
+    fn section(&self) -> usize {
+        match self {
+            Self::GloballyTrustedKey { .. } => 0,
+            Self::MissingSignedBy { .. } => 1,
+            Self::InsecureOption { .. } => 2,
+            Self::ExtraKey { .. } => 3,
+        }
+    }
+}
+
+/// Describe a source entry so the user can find it.
+fn describe_entry(entry: &InstalledEntry) -> String {
+    let values = |name: KnownOptionName| match entry.options.get(name) {
+        Some(value @ (OptionValue::String(_) | OptionValue::List(_))) => {
+            value.to_deb822().into_owned()
+        }
+        _ => String::new(),
+    };
+
+    format!(
+        "{}: {} {}",
+        entry.path.display(),
+        values(KnownOptionName::Uris),
+        values(KnownOptionName::Suites),
+    )
+}
+
+/// Return whether apt ignores this source entry because it's disabled.
+fn is_disabled(entry: &InstalledEntry) -> bool {
+    entry.options.get(KnownOptionName::Enabled) == Some(&OptionValue::Bool(false))
+}
+
+/// Find the keys in `keyring` which don't sign any of the `entries` that use it.
+///
+/// Entries that only trust specific keys in the keyring don't count, since apt ignores the other
+/// keys for them. We can only tell which key signs a source once apt has downloaded its release
+/// file, so if we can't tell for any of the sources using the keyring, this reports nothing.
+fn find_extra_keys(
+    client: &dyn PgpClient,
+    lists_dir: &Path,
+    keyring: &InstalledKeyring,
+    entries: &[InstalledEntry],
+) -> eyre::Result<Vec<KeyInfo>> {
+    let users = entries
+        .iter()
+        .filter(|entry| {
+            let signed_by = entry.signed_by();
+
+            keyring.is_used_by(&signed_by)
+                && !signed_by
+                    .iter()
+                    .any(|key| matches!(key, SignedBy::Fingerprint(_)))
+        })
+        .collect::<Vec<_>>();
+
+    if users.is_empty() || keyring.keys.len() < 2 {
+        return Ok(Vec::new());
+    }
+
+    let key = client
+        .read_key(&keyring.path, KeyEncoding::Binary)
+        .wrap_err_with(|| format!("failed reading keyring: {}", keyring.path.display()))?;
+
+    let mut finder = SignerFinder::new(client, lists_dir, key);
+    let mut signers = Vec::new();
+
+    for entry in users {
+        let entry_signers = finder.signers(&entry.options)?;
+
+        if entry_signers.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        signers.extend(entry_signers);
+    }
+
+    Ok(keyring
+        .keys
+        .iter()
+        .filter(|key| !signers.contains(&key.fingerprint))
+        .cloned()
+        .collect())
+}
+
+/// An audit of which signing keys apt trusts for each source.
+#[derive(Debug, Clone)]
+pub struct Audit {
+    findings: Vec<Finding>,
+}
+
+impl Audit {
+    /// Audit the sources in `sources_dir` and the keyrings in `locations`.
+    ///
+    /// The release files apt downloaded to `lists_dir` are used to work out which key signs each
+    /// source.
+    pub fn new(
+        client: &dyn PgpClient,
+        sources_dir: &Path,
+        locations: &KeyringLocations,
+        lists_dir: &Path,
+    ) -> eyre::Result<Self> {
+        let keyrings =
+            installed_keyrings(client, locations).wrap_err("failed reading installed keyrings")?;
+
+        let mut entries =
+            installed_entries(sources_dir).wrap_err("failed reading source entries")?;
+        entries.retain(|entry| !is_disabled(entry));
+
+        let mut findings = Vec::new();
+
+        for keyring in keyrings.iter().filter(|keyring| keyring.trusted) {
+            findings.extend(keyring.keys.iter().map(|key| Finding::GloballyTrustedKey {
+                keyring: keyring.path.clone(),
+                key: key.clone(),
+            }));
+        }
+
+        for entry in &entries {
+            if entry.signed_by().is_empty() {
+                findings.push(Finding::MissingSignedBy {
+                    entry: describe_entry(entry),
+                });
+            }
+
+            for option in INSECURE_OPTIONS {
+                if entry.options.get(option) == Some(&OptionValue::Bool(true)) {
+                    findings.push(Finding::InsecureOption {
+                        entry: describe_entry(entry),
+                        option,
+                    });
+                }
+            }
+        }
+
+        for keyring in keyrings.iter().filter(|keyring| !keyring.trusted) {
+            findings.extend(
+                find_extra_keys(client, lists_dir, keyring, &entries)?
+                    .into_iter()
+                    .map(|key| Finding::ExtraKey {
+                        keyring: keyring.path.clone(),
+                        key,
+                    }),
+            );
+        }
+
+        Ok(Self { findings })
+    }
+
+    /// The problems this audit found.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+}
+
+/// Write a key and its user IDs as an item in a list.
+fn write_key(f: &mut fmt::Formatter<'_>, keyring: &Path, key: &KeyInfo) -> fmt::Result {
+    writeln!(f, "  - {} in {}", key.fingerprint, keyring.display())?;
+
+    for user_id in &key.user_ids {
+        writeln!(f, "    {}", user_id)?;
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for Audit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (section, heading) in SECTION_HEADINGS.iter().enumerate() {
+            let findings = self
+                .findings
+                .iter()
+                .filter(|finding| finding.section() == section)
+                .collect::<Vec<_>>();
+
+            if findings.is_empty() {
+                continue;
+            }
+
+            writeln!(f, "{}", heading)?;
+
+            for finding in findings {
+                match finding {
+                    Finding::GloballyTrustedKey { keyring, key }
+                    | Finding::ExtraKey { keyring, key } => write_key(f, keyring, key)?,
+                    Finding::MissingSignedBy { entry } => writeln!(f, "  - {}", entry)?,
+                    Finding::InsecureOption { entry, option } => {
+                        writeln!(f, "  - {} ({}: yes)", entry, option.to_deb822())?
+                    }
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        match self.findings.len() {
+            0 => writeln!(f, "No problems found."),
+            1 => writeln!(f, "Found 1 problem."),
+            count => writeln!(f, "Found {} problems.", count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use xpct::{be_true, expect};
+
+    use crate::pgp::testing::{generate_key, gpg};
+    use crate::pgp::GnupgClient;
+
+    use super::*;
+
+    #[test]
+    fn flags_sources_which_trust_too_much() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+        let root = home.path();
+
+        let sources_dir = root.join("sources.list.d");
+        let lists_dir = root.join("lists");
+        let locations = KeyringLocations {
+            dirs: vec![root.join("keyrings")],
+            trusted_dir: root.join("trusted.gpg.d"),
+            trusted_file: root.join("trusted.gpg"),
+        };
+
+        for dir in [&sources_dir, &lists_dir, &locations.dirs[0]] {
+            fs::create_dir_all(dir)?;
+        }
+
+        let global_fingerprint = generate_key(root, "global <global@example.com>")?;
+        let repo_fingerprint = generate_key(root, "repo <repo@example.com>")?;
+        let extra_fingerprint = generate_key(root, "extra <extra@example.com>")?;
+
+        fs::write(
+            &locations.trusted_file,
+            gpg(root, &["--export", &global_fingerprint])?,
+        )?;
+
+        let keyring_path = locations.dirs[0].join("repo.gpg");
+        fs::write(
+            &keyring_path,
+            gpg(root, &["--export", &repo_fingerprint, &extra_fingerprint])?,
+        )?;
+
+        let release_path = root.join("Release");
+        fs::write(&release_path, "Origin: Example\n")?;
+
+        gpg(
+            root,
+            &[
+                "--local-user",
+                &repo_fingerprint,
+                "--output",
+                &lists_dir
+                    .join("example.com_debian_dists_stable_InRelease")
+                    .to_string_lossy(),
+                "--clearsign",
+                &release_path.to_string_lossy(),
+            ],
+        )?;
+
+        fs::write(
+            sources_dir.join("repo.sources"),
+            format!(
+                "Types: deb\nURIs: https://example.com/debian\nSuites: stable\nSigned-By: {}\n",
+                keyring_path.display()
+            ),
+        )?;
+        fs::write(
+            sources_dir.join("legacy.list"),
+            "deb [trusted=yes] https://example.org/debian stable main\n# deb https://example.net/debian stable main\n",
+        )?;
+
+        let client = GnupgClient::new("gpg");
+
+        let audit = Audit::new(&client, &sources_dir, &locations, &lists_dir)?;
+
+        expect!(matches!(
+            audit.findings(),
+            [
+                Finding::GloballyTrustedKey { .. },
+                Finding::MissingSignedBy { .. },
+                Finding::InsecureOption {
+                    option: KnownOptionName::Trusted,
+                    ..
+                },
+                Finding::ExtraKey { key, .. },
+            ] if key.fingerprint.to_string() == extra_fingerprint
+        ))
+        .to(be_true());
+
+        Ok(())
+    }
+}
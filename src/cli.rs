@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand};
 
-use crate::types::SourceType;
+use crate::types::{KeyFormat, SourceType};
 
 #[derive(Parser, Clone)]
 #[command(author, version, about)]
@@ -21,6 +21,100 @@ pub struct Cli {
     #[arg(long, value_name = "PATH", default_value = "/etc/apt/sources.list.d/")]
     pub sources_dir: PathBuf,
 
+    /// The keyservers to fetch signing keys from when you don't pass --keyserver
+    ///
+    /// These are tried in order until one of them returns the key. You can pass this multiple
+    /// times. This overrides the `Default-Keyservers` field in the config file, and by default,
+    /// hkps://keyserver.ubuntu.com and hkps://keys.openpgp.org are used.
+    #[arg(long, value_name = "URL")]
+    pub default_keyserver: Vec<String>,
+
+    /// How many seconds to wait to connect when downloading a signing key
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    pub connect_timeout: u64,
+
+    /// How many seconds to wait for a signing key to download before giving up
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    pub download_timeout: u64,
+
+    /// The largest signing key or keyring package to download or read from stdin, in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = 10 * 1024 * 1024)]
+    pub max_download_size: u64,
+
+    /// The path of the config file
+    ///
+    /// Settings passed on the command line override the ones in the config file. The config file
+    /// is optional unless you pass this.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// A PEM-encoded CA certificate bundle to trust when downloading
+    ///
+    /// These certificates are trusted in addition to the system's certificates. This overrides the
+    /// `CA-Cert` field in the config file.
+    #[arg(long, value_name = "PATH")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// A PEM-encoded client certificate to present when downloading
+    ///
+    /// This overrides the `Client-Cert` field in the config file. GnuPG can't present it to
+    /// keyservers, so hkps keyservers are skipped while it's set.
+    #[arg(long, value_name = "PATH", requires = "client_key")]
+    pub client_cert: Option<PathBuf>,
+
+    /// The PEM-encoded PKCS #8 private key for --client-cert
+    ///
+    /// This overrides the `Client-Key` field in the config file.
+    #[arg(long, value_name = "PATH", requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+
+    /// The proxy to use for downloads and keyserver lookups, or `DIRECT` to not use one
+    ///
+    /// By default, this uses the proxies configured for apt with `Acquire::http::Proxy` and
+    /// `Acquire::https::Proxy`, or the `http_proxy` and `https_proxy` environment variables if apt
+    /// doesn't have any.
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// The directory to read download credentials from, in apt's auth.conf format
+    ///
+    /// Credentials in this directory are used for the signing key URLs they match, the same way
+    /// apt uses them for repositories.
+    #[arg(long, value_name = "PATH", default_value = "/etc/apt/auth.conf.d/")]
+    pub auth_dir: PathBuf,
+
+    /// A netrc file to read download credentials from
+    ///
+    /// Credentials in --auth-dir take precedence over the ones in this file.
+    #[arg(long, value_name = "PATH")]
+    pub netrc: Option<PathBuf>,
+
+    /// The directory to cache downloaded signing keys in
+    ///
+    /// Every signing key and keyring package that's downloaded or received from a keyserver is
+    /// saved here, so it can be installed again with --offline. If this directory can't be
+    /// written to, keys are still installed but not cached.
+    #[arg(long, value_name = "PATH", default_value = "/var/cache/debctl")]
+    pub cache_dir: PathBuf,
+
+    /// Don't save downloaded signing keys to --cache-dir
+    #[arg(long, conflicts_with = "offline")]
+    pub no_cache: bool,
+
+    /// Don't access the network; only use signing keys from --cache-dir or local files
+    ///
+    /// This fails if a signing key would need to be downloaded or received from a keyserver and
+    /// isn't in the cache.
+    #[arg(long)]
+    pub offline: bool,
+
+    /// An HTTP header to send when downloading signing keys, like `Authorization: Bearer TOKEN`
+    ///
+    /// This is only sent to the server the signing key URL points to, not to servers it redirects
+    /// to. You can pass this multiple times.
+    #[arg(long, value_name = "HEADER")]
+    pub key_auth_header: Vec<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -32,10 +126,19 @@ pub struct KeySourceArgs {
     ///
     /// This accepts the URL or local file path of a PGP key, in either binary or armored format.
     /// The key is downloaded and installed to /etc/apt/keyrings unless you pass --key-path.
+    /// `file://` URLs are read as local files, and `data:` URLs can embed the key itself.
+    ///
+    /// This can also be the URL or local file path of a vendor's .deb keyring package, in which
+    /// case the keyring is extracted from the package.
+    ///
+    /// Pass `-` to read the key from stdin. Keys read from stdin can't be refreshed later.
     ///
-    /// If you pass --keyserver, this is the key fingerprint.
+    /// This can also be the full fingerprint of a key to fetch from a keyserver.
+    ///
+    /// You can pass this multiple times, such as when a repository is rotating its signing key and
+    /// both the old and new keys need to be trusted. The keys are combined into a single keyring.
     #[arg(short, long)]
-    pub key: Option<String>,
+    pub key: Vec<String>,
 
     /// Do not install the public signing key for the repo
     ///
@@ -46,7 +149,25 @@ pub struct KeySourceArgs {
 }
 
 #[derive(Args, Clone)]
-#[group(required = false, multiple = false)]
+pub struct KeyFetchArgs {
+    /// Allow downloading signing keys over plain HTTP
+    ///
+    /// By default, signing keys can only be downloaded over HTTPS, since a key downloaded over
+    /// plain HTTP could have been replaced by anyone on the network path. Only pass this if you
+    /// can verify the key some other way, such as with --key-fingerprint.
+    #[arg(long)]
+    pub allow_insecure_key_url: bool,
+
+    /// The keyring to install from a .deb keyring package
+    ///
+    /// When --key is a vendor's keyring package, like `foo-archive-keyring_1.0_all.deb`, the
+    /// keyring is extracted from /usr/share/keyrings or /etc/apt/trusted.gpg.d in the package. If
+    /// the package contains more than one keyring, pass its file name or path to choose one.
+    #[arg(long, value_name = "NAME")]
+    pub package_keyring: Option<String>,
+}
+
+#[derive(Args, Clone)]
 pub struct KeyDestinationArgs {
     /// The path to install the repository signing key to
     ///
@@ -56,8 +177,33 @@ pub struct KeyDestinationArgs {
 
     /// Inline the repository signing key into the source entry instead of installing it to a
     /// separate file
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "key_path")]
     pub inline_key: bool,
+
+    /// The file format to install the repository signing key in
+    ///
+    /// Armored keys are installed with a `.asc` extension and binary keys with a `.gpg` extension.
+    /// By default, this is inferred from the extension of --key-path, or binary otherwise.
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "inline_key")]
+    pub key_format: Option<KeyFormat>,
+}
+
+#[derive(Args, Clone)]
+pub struct KeyserverArgs {
+    /// Download the repository signing key from this keyserver
+    ///
+    /// If this option is passed, any --key that isn't a URL or file path is interpreted as a key
+    /// fingerprint. You can pass this multiple times to try each keyserver in order until one of
+    /// them returns the key.
+    #[arg(long, value_name = "URL")]
+    pub keyserver: Vec<String>,
+
+    /// Accept a long key ID instead of a full fingerprint when fetching from a keyserver
+    ///
+    /// Long key IDs are much harder to forge than short key IDs, but they're still less secure
+    /// than a full fingerprint. Short key IDs are never accepted.
+    #[arg(long)]
+    pub allow_long_key_id: bool,
 }
 
 #[derive(Args, Clone)]
@@ -65,16 +211,85 @@ pub struct SigningKeyArgs {
     #[command(flatten)]
     pub location: KeySourceArgs,
 
-    /// Download the repository signing key from this keyserver
+    #[command(flatten)]
+    pub keyserver: KeyserverArgs,
+
+    #[command(flatten)]
+    pub fetch: KeyFetchArgs,
+
+    /// Strip third-party signatures from the repository signing key
     ///
-    /// If this option is passed, --key is interpreted as the key fingerprint.
-    #[arg(long, value_name = "URL")]
-    pub keyserver: Option<String>,
+    /// This installs only the primary key, its valid subkeys, and their self-signatures. Keys
+    /// fetched from keyservers often carry many third-party signatures, which apt doesn't use.
+    ///
+    /// This is the default with --inline-key.
+    #[arg(long, conflicts_with = "no_minimal_key")]
+    pub minimal_key: bool,
+
+    /// Keep third-party signatures on the repository signing key
+    ///
+    /// This is the default unless you pass --inline-key.
+    #[arg(long, conflicts_with = "minimal_key")]
+    pub no_minimal_key: bool,
+
+    /// Only trust the key with this fingerprint to sign the repository
+    ///
+    /// This is useful when the signing key contains several keys, but only one of them should sign
+    /// this repository. The key must be a key or subkey in the signing key passed with --key. You
+    /// can pass this multiple times.
+    ///
+    /// Signatures from subkeys of this key are also trusted unless you append a `!` to the
+    /// fingerprint.
+    #[arg(
+        long,
+        value_name = "FINGERPRINT",
+        requires = "key",
+        conflicts_with = "inline_key"
+    )]
+    pub key_fingerprint: Vec<String>,
+
+    /// Overwrite an existing signing key file that contains a different key
+    ///
+    /// By default, installing a signing key fails if there's already a file at the destination
+    /// which contains a different key, because other sources may rely on it.
+    #[arg(long, conflicts_with = "inline_key")]
+    pub overwrite_key: bool,
+
+    /// Reuse an identical signing key that's already installed under another name
+    ///
+    /// If a file in the same directory as the key destination already contains exactly the same
+    /// keys, point this source entry at that file instead of installing a duplicate.
+    #[arg(long, conflicts_with = "inline_key")]
+    pub reuse_existing_key: bool,
+
+    /// Fail if apt would consider the signing key weak
+    ///
+    /// apt warns about repositories signed with RSA keys smaller than 2048 bits, DSA keys, and
+    /// keys with SHA-1 self-signatures. By default, this only prints a warning.
+    #[arg(long)]
+    pub strict: bool,
 
     #[command(flatten)]
     pub destination: KeyDestinationArgs,
 }
 
+#[derive(Args, Clone)]
+pub struct CredentialsArgs {
+    /// The username to authenticate to the repository with
+    ///
+    /// The credentials are written to /etc/apt/auth.conf.d/ instead of the source file, where only
+    /// root can read them. Credentials embedded in the repository URIs are moved there too.
+    #[arg(long, requires = "password_file")]
+    pub username: Option<String>,
+
+    /// A file containing the password to authenticate to the repository with
+    ///
+    /// The password is read from a file so it doesn't end up in your shell history or the process
+    /// list. A trailing newline is ignored.
+    #[arg(long, value_name = "PATH", requires = "username")]
+    pub password_file: Option<PathBuf>,
+}
+
 #[derive(Args, Clone)]
 pub struct DescriptionArgs {
     /// A human-readable name for the source entry
@@ -138,6 +353,9 @@ pub struct New {
     #[command(flatten)]
     pub key: SigningKeyArgs,
 
+    #[command(flatten)]
+    pub credentials: CredentialsArgs,
+
     /// The architectures to include
     #[arg(long)]
     pub arch: Vec<String>,
@@ -186,6 +404,9 @@ pub struct Add {
     #[command(flatten)]
     pub key: SigningKeyArgs,
 
+    #[command(flatten)]
+    pub credentials: CredentialsArgs,
+
     #[command(flatten)]
     pub disabled: DisabledArgs,
 
@@ -254,6 +475,116 @@ pub struct Convert {
     pub skip_disabled: bool,
 }
 
+#[derive(Args, Clone)]
+pub struct Remove {
+    /// The name of the source file
+    ///
+    /// This is the basename of the source file in /etc/apt/sources.list.d/.
+    #[arg(short, long)]
+    pub name: String,
+}
+
+#[derive(Args, Clone)]
+pub struct Audit {}
+
+#[derive(Args, Clone)]
+pub struct KeyList {}
+
+#[derive(Args, Clone)]
+pub struct KeyRefresh {
+    /// Only refresh the signing keys for the source with this name
+    ///
+    /// This is the basename of the source file in /etc/apt/sources.list.d/. By default, the
+    /// signing keys for every source are refreshed.
+    #[arg(short, long)]
+    pub name: Option<String>,
+
+    /// Allow downloading signing keys over plain HTTP
+    ///
+    /// By default, signing keys that were installed from a plain HTTP URL aren't refreshed, since
+    /// a key downloaded over plain HTTP could have been replaced by anyone on the network path.
+    #[arg(long)]
+    pub allow_insecure_key_url: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct KeyRotate {
+    /// The name of the source to rotate the signing key for
+    ///
+    /// This is the basename of the source file in /etc/apt/sources.list.d/.
+    #[arg(short, long)]
+    pub name: String,
+
+    /// The new public signing key for the repo
+    ///
+    /// This accepts the same values as --key for `debctl new`. You can pass this multiple times.
+    #[arg(short, long, required = true)]
+    pub key: Vec<String>,
+
+    #[command(flatten)]
+    pub keyserver: KeyserverArgs,
+
+    #[command(flatten)]
+    pub fetch: KeyFetchArgs,
+}
+
+#[derive(Args, Clone)]
+pub struct KeyMigrate {
+    /// Remove the migrated keys from the globally trusted keyrings without asking
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum KeyCommands {
+    /// List the installed signing keys and which sources use them
+    ///
+    /// This lists the keyrings in /etc/apt/keyrings, /usr/share/keyrings, and
+    /// /etc/apt/trusted.gpg.d, along with the legacy /etc/apt/trusted.gpg keyring.
+    ///
+    /// Keyrings that aren't referenced by the `Signed-By` option of any source are flagged as
+    /// orphaned. Keyrings that apt trusts for every source are flagged as globally trusted.
+    List(KeyList),
+
+    /// Fetch signing keys again from where they were originally installed from
+    ///
+    /// This picks up changes like extended expiration dates and new subkeys. It only works for
+    /// keys that debctl installed to a separate file, since debctl records where those keys came
+    /// from in the source entry.
+    ///
+    /// The keyring is only replaced if its primary keys are unchanged. If the primary keys are
+    /// different, the repository may have replaced its signing key, and you'll need to install the
+    /// new key yourself.
+    Refresh(KeyRefresh),
+
+    /// Replace the signing key for a source with a new one
+    ///
+    /// The first time you run this, the new key is installed alongside the old one, so the
+    /// repository can be signed by either of them while it transitions to the new key. Run it again
+    /// with the same arguments once the repository is signed by the new key to remove the old one.
+    ///
+    /// This only works for deb822-style sources whose `Signed-By` option references a separate
+    /// key file.
+    Rotate(KeyRotate),
+
+    /// Move keys installed with `apt-key` out of the globally trusted keyrings
+    ///
+    /// This checks which key signs each source by verifying the release files apt downloaded to
+    /// /var/lib/apt/lists, so run `apt update` first. Each of those keys is installed to its own
+    /// file in /etc/apt/keyrings, and the sources it signs are updated to reference that file with
+    /// the `Signed-By` option.
+    ///
+    /// Afterwards, you're asked whether to remove the migrated keys from /etc/apt/trusted.gpg and
+    /// /etc/apt/trusted.gpg.d. Keys that don't sign any source are left in place.
+    Migrate(KeyMigrate),
+}
+
+#[derive(Args, Clone)]
+pub struct Key {
+    #[command(subcommand)]
+    pub command: KeyCommands,
+}
+
 #[derive(Subcommand, Clone)]
 pub enum Commands {
     /// Add a new source entry by specifying its parameters
@@ -278,4 +609,26 @@ pub enum Commands {
     ///
     /// You must pass either --name or both --in and --out.
     Convert(Convert),
+
+    /// Remove a source along with its credentials and signing keys
+    ///
+    /// This deletes the source file in /etc/apt/sources.list.d/ with this name, its credentials in
+    /// /etc/apt/auth.conf.d/, and the key files in /etc/apt/keyrings/ its `Signed-By` option
+    /// references. Key files that other sources also use are left in place.
+    Remove(Remove),
+
+    /// Check which signing keys apt trusts for each source
+    ///
+    /// This flags keys in /etc/apt/trusted.gpg and /etc/apt/trusted.gpg.d, which apt trusts for
+    /// every source, along with sources that don't have a `Signed-By` option and sources with
+    /// options like `Trusted: yes` or `Allow-Insecure: yes` that weaken signature checks.
+    ///
+    /// It also flags keys in a `Signed-By` keyring that don't sign any of the sources using it,
+    /// based on the release files apt downloaded to /var/lib/apt/lists.
+    ///
+    /// This exits with a non-zero status if it finds any problems.
+    Audit(Audit),
+
+    /// Manage repository signing keys
+    Key(Key),
 }
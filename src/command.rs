@@ -1,13 +1,35 @@
-use std::fmt::Write;
-use std::path::PathBuf;
-
-use crate::args::{AddArgs, ConvertArgs, NewArgs, OverwriteAction};
+use std::fmt::{self, Write};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use eyre::{bail, WrapErr};
+
+use crate::args::{AddArgs, ConvertArgs, KeyArgOptions, KeyRotateArgs, NewArgs, OverwriteAction};
+use crate::audit::Audit;
+use crate::auth::AuthFile;
+use crate::cache::KeyCache;
 use crate::cli;
+use crate::config::{ClientCertificate, ConfigFile, DEFAULT_CONFIG_PATH, DEFAULT_KEYSERVERS};
 use crate::convert::EntryConverter;
-use crate::entry::SourceEntry;
-use crate::file::{SourceFile, SourceFileKind, SourceFilePath};
-use crate::key::KeyDest;
-use crate::pgp::{GnupgClient, PgpClient};
+use crate::entry::{InstallPlan, SourceEntry};
+use crate::error::Error;
+use crate::file::{write_atomically, SourceFile, SourceFileKind, SourceFilePath};
+use crate::key::{
+    refresh_keys, rotate_keys, KeyDest, KeyFileDest, KeyInstallAction, KeyRefreshAction,
+    KeyRotateAction, KeySource, KeyWeakness,
+};
+use crate::keyring::{installed_keyrings, KeyringListing, KeyringLocations};
+use crate::lists::LISTS_DIR;
+use crate::migrate::{MigrateLocations, MigrationPlan};
+use crate::net::{DownloadOptions, HttpClient, ReqwestClient};
+use crate::parse::{parse_deb822_file, Deb822Entry};
+use crate::pgp::{Fingerprint, GnupgClient, KeyExportMode, PgpClient};
+use crate::proxy::ProxyConfig;
+use crate::remove::{RemovalPlan, RemoveLocations};
+use crate::sources::{installed_entries, update_deb822_contents, InstalledEntry};
 
 /// High-level configuration for the program.
 pub struct Config {
@@ -16,13 +38,133 @@ pub struct Config {
 
     /// The path of the APT sources directory.
     pub sources_dir: PathBuf,
+
+    /// The keyservers to try, in order, when the user doesn't specify one.
+    pub default_keyservers: Vec<String>,
+
+    /// How to download signing keys.
+    pub download: DownloadOptions,
+
+    /// The path of apt's `auth.conf.d` directory.
+    pub auth_dir: PathBuf,
+
+    /// Where to cache signing keys, and whether to only use cached keys.
+    pub cache: KeyCache,
+
+    /// The proxy to use for every URL, from `--proxy`.
+    ///
+    /// If this isn't set, apt's proxy settings are used.
+    pub proxy: Option<String>,
 }
 
 impl Config {
-    /// Create a new PGP client.
+    /// Read the proxy settings.
+    ///
+    /// This is only done for commands which access the network, so a broken proxy setting doesn't
+    /// break commands which don't.
+    fn proxy_config(&self) -> eyre::Result<ProxyConfig> {
+        match &self.proxy {
+            Some(proxy) => Ok(ProxyConfig::all(proxy.parse()?)),
+            None => ProxyConfig::from_apt_config(),
+        }
+    }
+
+    /// Create a new PGP client which doesn't access the network.
     pub fn pgp_client(&self) -> Box<dyn PgpClient> {
         Box::new(GnupgClient::new(&self.gpg_path))
     }
+
+    /// Create a new PGP client which can receive keys from keyservers.
+    pub fn keyserver_client(&self) -> eyre::Result<Box<dyn PgpClient>> {
+        Ok(Box::new(
+            GnupgClient::new(&self.gpg_path)
+                .with_proxy(self.proxy_config()?)
+                .with_client_cert(self.download.client_cert.is_some()),
+        ))
+    }
+
+    /// The settings for parsing `--key` args, which check keys with `client`.
+    pub fn key_arg_options<'a>(&'a self, client: &'a dyn PgpClient) -> KeyArgOptions<'a> {
+        KeyArgOptions {
+            default_keyservers: &self.default_keyservers,
+            max_stdin_size: self.download.max_size,
+            client,
+        }
+    }
+
+    /// Create a new HTTP client for downloading signing keys.
+    pub fn http_client(&self) -> eyre::Result<Box<dyn HttpClient>> {
+        let options = DownloadOptions {
+            proxy: self.proxy_config()?,
+            ..self.download.clone()
+        };
+
+        Ok(Box::new(ReqwestClient::new(&options)?))
+    }
+}
+
+/// Write a report of which signing keys were installed where.
+///
+/// The `action` is what happened to the key file, if we know.
+fn write_key_report(
+    output: &mut String,
+    dest: &KeyDest,
+    keys: &[KeySource],
+    action: Option<&KeyInstallAction>,
+) -> fmt::Result {
+    if keys.is_empty() {
+        return Ok(());
+    }
+
+    match (dest, action) {
+        (KeyDest::File(KeyFileDest { path, .. }), Some(KeyInstallAction::Overwrite)) => {
+            writeln!(output, "Overwrote existing signing key: {}", path.display())?
+        }
+        (KeyDest::File(KeyFileDest { path, .. }), Some(KeyInstallAction::Unchanged)) => {
+            writeln!(output, "Signing key already installed: {}", path.display())?
+        }
+        (KeyDest::File(_), Some(KeyInstallAction::Reuse { path })) => {
+            writeln!(output, "Reused existing signing key: {}", path.display())?
+        }
+        (KeyDest::File(KeyFileDest { path, .. }), _) => {
+            writeln!(output, "Installed signing key: {}", path.display())?
+        }
+        (KeyDest::Inline { .. }, _) => writeln!(output, "Inlined signing key into source entry")?,
+    }
+
+    for key in keys {
+        writeln!(output, "  - {}", key)?;
+    }
+
+    Ok(())
+}
+
+/// Write a warning about why apt would consider the installed signing keys weak, if it would.
+fn write_weak_key_warning(output: &mut String, weaknesses: &[KeyWeakness]) -> fmt::Result {
+    if weaknesses.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(output, "Warning: apt considers this signing key weak:")?;
+
+    for weakness in weaknesses {
+        writeln!(output, "  - {}", weakness)?;
+    }
+
+    Ok(())
+}
+
+/// Write a report of where the repository credentials were installed.
+fn write_credentials_report(output: &mut String, auth_file: &AuthFile) -> fmt::Result {
+    if !auth_file.is_empty() {
+        writeln!(
+            output,
+            "Installed repository credentials: {}",
+            auth_file.path().display()
+        )?;
+    }
+
+    Ok(())
 }
 
 /// A CLI command.
@@ -32,40 +174,79 @@ pub trait Command {
 
     /// Return an optional report of what the command did, to print to stdout.
     fn report(&self) -> eyre::Result<Option<String>>;
+
+    /// The exit code to return once the command has run.
+    ///
+    /// Commands which check the system can use this to signal that they found problems.
+    fn exit_code(&self) -> ExitCode {
+        ExitCode::SUCCESS
+    }
 }
 
 pub struct NewCommand {
     client: Box<dyn PgpClient>,
+    http: Box<dyn HttpClient>,
+    cache: KeyCache,
     action: OverwriteAction,
     key_dest: KeyDest,
+    key_action: Option<KeyInstallAction>,
+    auth_file: AuthFile,
     entry: SourceEntry,
     source_file: SourceFile,
+    plan: InstallPlan,
 }
 
 impl NewCommand {
     pub fn new(args: cli::New, conf: Config) -> eyre::Result<Self> {
-        let new_args = NewArgs::from_cli(args)?;
+        let new_args = NewArgs::from_cli(args, &conf.key_arg_options(conf.pgp_client().as_ref()))?;
+
+        let entry = SourceEntry::from_new(&new_args)?;
+
+        let source_file = SourceFile {
+            path: SourceFilePath::Installed {
+                name: new_args.name().to_owned(),
+                dir: conf.sources_dir.clone(),
+            },
+            kind: SourceFileKind::Deb822,
+        };
+
+        // We need to plan the installation before we run the command, because afterwards the
+        // source file will already exist.
+        let plan = entry.plan(&source_file, new_args.action())?;
 
         Ok(Self {
-            client: conf.pgp_client(),
+            client: conf.keyserver_client()?,
+            http: conf.http_client()?,
+            cache: conf.cache.clone(),
             action: new_args.action(),
             key_dest: new_args.key().dest.clone(),
-            entry: SourceEntry::from_new(&new_args)?,
-            source_file: SourceFile {
-                path: SourceFilePath::Installed {
-                    name: new_args.name().to_owned(),
-                    dir: conf.sources_dir,
-                },
-                kind: SourceFileKind::Deb822,
-            },
+            key_action: None,
+            auth_file: AuthFile::new(
+                &conf.auth_dir,
+                new_args.name(),
+                new_args.credentials().to_vec(),
+            )?,
+            entry,
+            source_file,
+            plan,
         })
     }
 }
 
 impl Command for NewCommand {
     fn run(&mut self) -> eyre::Result<()> {
-        self.entry
-            .install_key(self.client.as_ref(), &self.key_dest)?;
+        self.key_action = self.entry.install_key(
+            self.client.as_ref(),
+            self.http.as_ref(),
+            &self.cache,
+            &self.key_dest,
+        )?;
+
+        if !self.auth_file.is_empty() {
+            self.auth_file
+                .install(self.action == OverwriteAction::Append)?;
+        }
+
         self.entry.install(&self.source_file, self.action)?;
 
         Ok(())
@@ -74,52 +255,83 @@ impl Command for NewCommand {
     fn report(&self) -> eyre::Result<Option<String>> {
         let mut output = String::new();
 
-        if let KeyDest::File { path } = &self.key_dest {
-            writeln!(&mut output, "Installed signing key: {}", path.display())?;
-        }
-
-        write!(
+        write_key_report(
             &mut output,
-            "{}",
-            self.entry.plan(&self.source_file, self.action)?
+            &self.key_dest,
+            self.entry.keys(),
+            self.key_action.as_ref(),
         )?;
 
+        write_weak_key_warning(&mut output, self.entry.key_weaknesses())?;
+
+        write_credentials_report(&mut output, &self.auth_file)?;
+
+        write!(&mut output, "{}", self.plan)?;
+
         Ok(Some(output))
     }
 }
 
 pub struct AddCommand {
     client: Box<dyn PgpClient>,
+    http: Box<dyn HttpClient>,
+    cache: KeyCache,
     action: OverwriteAction,
     key_dest: KeyDest,
+    key_action: Option<KeyInstallAction>,
+    auth_file: AuthFile,
     entry: SourceEntry,
     source_file: SourceFile,
+    plan: InstallPlan,
 }
 
 impl AddCommand {
     pub fn new(args: cli::Add, conf: Config) -> eyre::Result<Self> {
-        let add_args = AddArgs::from_cli(args)?;
+        let add_args = AddArgs::from_cli(args, &conf.key_arg_options(conf.pgp_client().as_ref()))?;
+
+        let entry = SourceEntry::from_add(&add_args)?;
+
+        let source_file = SourceFile {
+            path: SourceFilePath::Installed {
+                name: add_args.name().to_owned(),
+                dir: conf.sources_dir.clone(),
+            },
+            kind: SourceFileKind::Deb822,
+        };
+
+        // We need to plan the installation before we run the command, because afterwards the
+        // source file will already exist.
+        let plan = entry.plan(&source_file, add_args.action())?;
 
         Ok(Self {
-            client: conf.pgp_client(),
+            client: conf.keyserver_client()?,
+            http: conf.http_client()?,
+            cache: conf.cache.clone(),
             action: add_args.action(),
             key_dest: add_args.key().dest.clone(),
-            entry: SourceEntry::from_add(&add_args)?,
-            source_file: SourceFile {
-                path: SourceFilePath::Installed {
-                    name: add_args.name().to_owned(),
-                    dir: conf.sources_dir,
-                },
-                kind: SourceFileKind::Deb822,
-            },
+            key_action: None,
+            auth_file: AuthFile::new(&conf.auth_dir, add_args.name(), add_args.credentials()?)?,
+            entry,
+            source_file,
+            plan,
         })
     }
 }
 
 impl Command for AddCommand {
     fn run(&mut self) -> eyre::Result<()> {
-        self.entry
-            .install_key(self.client.as_ref(), &self.key_dest)?;
+        self.key_action = self.entry.install_key(
+            self.client.as_ref(),
+            self.http.as_ref(),
+            &self.cache,
+            &self.key_dest,
+        )?;
+
+        if !self.auth_file.is_empty() {
+            self.auth_file
+                .install(self.action == OverwriteAction::Append)?;
+        }
+
         self.entry.install(&self.source_file, self.action)?;
 
         Ok(())
@@ -128,16 +340,19 @@ impl Command for AddCommand {
     fn report(&self) -> eyre::Result<Option<String>> {
         let mut output = String::new();
 
-        if let KeyDest::File { path } = &self.key_dest {
-            writeln!(&mut output, "Installed signing key: {}", path.display())?;
-        }
-
-        write!(
+        write_key_report(
             &mut output,
-            "{}",
-            self.entry.plan(&self.source_file, self.action)?
+            &self.key_dest,
+            self.entry.keys(),
+            self.key_action.as_ref(),
         )?;
 
+        write_weak_key_warning(&mut output, self.entry.key_weaknesses())?;
+
+        write_credentials_report(&mut output, &self.auth_file)?;
+
+        write!(&mut output, "{}", self.plan)?;
+
         Ok(Some(output))
     }
 }
@@ -149,7 +364,11 @@ pub struct ConvertCommand {
 impl ConvertCommand {
     pub fn new(args: cli::Convert, conf: Config) -> eyre::Result<Self> {
         Ok(Self {
-            converter: EntryConverter::new(&ConvertArgs::from_cli(&args)?, conf.sources_dir)?,
+            converter: EntryConverter::new(
+                &ConvertArgs::from_cli(&args)?,
+                conf.sources_dir,
+                &conf.auth_dir,
+            )?,
         })
     }
 }
@@ -170,21 +389,527 @@ impl Command for ConvertCommand {
     }
 }
 
+pub struct KeyListCommand {
+    listing: KeyringListing,
+}
+
+impl KeyListCommand {
+    pub fn new(_args: cli::KeyList, conf: Config) -> eyre::Result<Self> {
+        let client = conf.pgp_client();
+
+        let keyrings = installed_keyrings(client.as_ref(), &KeyringLocations::default())
+            .wrap_err("failed reading installed keyrings")?;
+        let entries =
+            installed_entries(&conf.sources_dir).wrap_err("failed reading source entries")?;
+
+        Ok(Self {
+            listing: KeyringListing::new(keyrings, &entries),
+        })
+    }
+}
+
+impl Command for KeyListCommand {
+    fn run(&mut self) -> eyre::Result<()> {
+        // Everything this command reports is gathered when it's created.
+        Ok(())
+    }
+
+    fn report(&self) -> eyre::Result<Option<String>> {
+        Ok(Some(self.listing.to_string()))
+    }
+}
+
+/// A keyring to refresh and where its keys came from.
+struct RefreshTarget {
+    path: PathBuf,
+    sources: Vec<KeySource>,
+    mode: KeyExportMode,
+    fingerprints: Vec<Fingerprint>,
+}
+
+pub struct KeyRefreshCommand {
+    client: Box<dyn PgpClient>,
+    http: Box<dyn HttpClient>,
+    cache: KeyCache,
+    targets: Vec<RefreshTarget>,
+    actions: Vec<KeyRefreshAction>,
+}
+
+impl KeyRefreshCommand {
+    pub fn new(args: cli::KeyRefresh, conf: Config) -> eyre::Result<Self> {
+        let entries =
+            installed_entries(&conf.sources_dir).wrap_err("failed reading source entries")?;
+
+        let mut targets: Vec<RefreshTarget> = Vec::new();
+
+        for entry in entries {
+            if let Some(name) = &args.name {
+                if entry.file_name() != Some(name.as_str()) {
+                    continue;
+                }
+            }
+
+            let sources = entry.key_sources().wrap_err_with(|| {
+                format!("failed reading key sources: {}", entry.path.display())
+            })?;
+
+            for source in &sources {
+                source.check_url(args.allow_insecure_key_url)?;
+            }
+
+            let path = match entry.keyring_path() {
+                Some(path) if !sources.is_empty() => path,
+                _ => continue,
+            };
+
+            // Several sources can share a keyring, but it only needs to be refreshed once.
+            if !targets.iter().any(|target| target.path == path) {
+                targets.push(RefreshTarget {
+                    path,
+                    sources,
+                    mode: entry.key_export_mode(),
+                    fingerprints: entry.trusted_fingerprints(),
+                });
+            }
+        }
+
+        if let (Some(name), true) = (args.name, targets.is_empty()) {
+            bail!(Error::NoKeysToRefresh { name });
+        }
+
+        Ok(Self {
+            client: conf.keyserver_client()?,
+            http: conf.http_client()?,
+            cache: conf.cache.clone(),
+            targets,
+            actions: Vec::new(),
+        })
+    }
+}
+
+impl Command for KeyRefreshCommand {
+    fn run(&mut self) -> eyre::Result<()> {
+        for target in &self.targets {
+            let action = refresh_keys(
+                &target.sources,
+                self.client.as_ref(),
+                self.http.as_ref(),
+                &self.cache,
+                &target.path,
+                target.mode,
+                &target.fingerprints,
+            )
+            .wrap_err_with(|| format!("failed refreshing keyring: {}", target.path.display()))?;
+
+            self.actions.push(action);
+        }
+
+        Ok(())
+    }
+
+    fn report(&self) -> eyre::Result<Option<String>> {
+        let mut output = String::new();
+
+        if self.targets.is_empty() {
+            writeln!(&mut output, "There are no signing keys to refresh.")?;
+        }
+
+        for (index, target) in self.targets.iter().enumerate() {
+            match self.actions.get(index) {
+                Some(KeyRefreshAction::Update) => writeln!(
+                    &mut output,
+                    "Refreshed signing key: {}",
+                    target.path.display()
+                )?,
+                Some(KeyRefreshAction::Unchanged) => writeln!(
+                    &mut output,
+                    "Signing key is already up to date: {}",
+                    target.path.display()
+                )?,
+                None => writeln!(
+                    &mut output,
+                    "Would refresh signing key: {}",
+                    target.path.display()
+                )?,
+            }
+
+            for source in &target.sources {
+                writeln!(&mut output, "  - {}", source)?;
+            }
+        }
+
+        Ok(Some(output))
+    }
+}
+
+pub struct KeyRotateCommand {
+    client: Box<dyn PgpClient>,
+    http: Box<dyn HttpClient>,
+    cache: KeyCache,
+    source_path: PathBuf,
+    key_path: PathBuf,
+    key_mode: KeyExportMode,
+    key_sources: Vec<KeySource>,
+    contents: String,
+    entries: Vec<Deb822Entry>,
+    action: Option<KeyRotateAction>,
+}
+
+impl KeyRotateCommand {
+    pub fn new(args: cli::KeyRotate, conf: Config) -> eyre::Result<Self> {
+        let rotate_args =
+            KeyRotateArgs::from_cli(&args, &conf.key_arg_options(conf.pgp_client().as_ref()))?;
+
+        let source_file = SourceFile {
+            path: SourceFilePath::Installed {
+                name: rotate_args.name().to_owned(),
+                dir: conf.sources_dir.clone(),
+            },
+            kind: SourceFileKind::Deb822,
+        };
+
+        let source_path = source_file.path().into_owned();
+
+        let contents = match fs::read_to_string(&source_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                bail!(Error::SourceFileNotFound { path: source_path })
+            }
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                bail!(Error::PermissionDenied)
+            }
+            Err(err) => return Err(err).wrap_err("failed reading source file"),
+        };
+
+        let entries = parse_deb822_file(contents.as_bytes(), false)?;
+
+        let mut key_paths: Vec<(PathBuf, KeyExportMode)> = Vec::new();
+
+        for entry in &entries {
+            if let Deb822Entry::Entry(options) = entry {
+                let installed = InstalledEntry {
+                    path: source_path.clone(),
+                    options: options.clone(),
+                };
+
+                if let Some(path) = installed.keyring_path() {
+                    if !key_paths.iter().any(|(key_path, _)| key_path == &path) {
+                        key_paths.push((path, installed.key_export_mode()));
+                    }
+                }
+            }
+        }
+
+        let (key_path, key_mode) = match key_paths.as_slice() {
+            [] => bail!(Error::NoKeyToRotate {
+                name: rotate_args.name().to_owned()
+            }),
+            [(path, mode)] => (path.clone(), *mode),
+            paths => bail!(Error::MultipleKeyFiles {
+                name: rotate_args.name().to_owned(),
+                paths: paths
+                    .iter()
+                    .map(|(path, _)| path.display().to_string())
+                    .collect(),
+            }),
+        };
+
+        // Only this source file is updated, so other sources using the same key file would be left
+        // trusting keys that are no longer in it.
+        let mut shared_with = installed_entries(&conf.sources_dir)
+            .wrap_err("failed reading source entries")?
+            .into_iter()
+            .filter(|entry| {
+                entry.path != source_path && entry.keyring_path().as_ref() == Some(&key_path)
+            })
+            .map(|entry| entry.path.display().to_string())
+            .collect::<Vec<_>>();
+
+        shared_with.dedup();
+
+        if !shared_with.is_empty() {
+            bail!(Error::SharedKeyFile {
+                path: key_path,
+                sources: shared_with,
+            });
+        }
+
+        Ok(Self {
+            client: conf.keyserver_client()?,
+            http: conf.http_client()?,
+            cache: conf.cache.clone(),
+            source_path,
+            key_path,
+            key_mode,
+            key_sources: rotate_args.sources().to_vec(),
+            contents,
+            entries,
+            action: None,
+        })
+    }
+}
+
+impl Command for KeyRotateCommand {
+    fn run(&mut self) -> eyre::Result<()> {
+        let (action, fingerprints) = rotate_keys(
+            &self.key_sources,
+            self.client.as_ref(),
+            self.http.as_ref(),
+            &self.cache,
+            &self.key_path,
+            self.key_mode,
+        )
+        .wrap_err_with(|| format!("failed rotating keyring: {}", self.key_path.display()))?;
+
+        let mut entries = self.entries.clone();
+
+        for entry in &mut entries {
+            if let Deb822Entry::Entry(options) = entry {
+                let mut installed = InstalledEntry {
+                    path: self.source_path.clone(),
+                    options: options.clone(),
+                };
+
+                if installed.keyring_path().as_ref() == Some(&self.key_path) {
+                    installed.rotate_keys(action, &self.key_sources, &fingerprints);
+                    *options = installed.options;
+                }
+            }
+        }
+
+        if entries != self.entries {
+            let contents = update_deb822_contents(&self.contents, &self.entries, &entries)?;
+
+            write_atomically(&self.source_path, &contents)
+                .wrap_err("failed writing source file")?;
+        }
+
+        self.action = Some(action);
+
+        Ok(())
+    }
+
+    fn report(&self) -> eyre::Result<Option<String>> {
+        let mut output = String::new();
+
+        let path = self.key_path.display();
+
+        match self.action {
+            Some(KeyRotateAction::AddNew) => writeln!(
+                &mut output,
+                "Added new signing key alongside the old one: {}",
+                path
+            )?,
+            Some(KeyRotateAction::RemoveOld) => {
+                writeln!(&mut output, "Removed old signing key: {}", path)?
+            }
+            Some(KeyRotateAction::Unchanged) => {
+                writeln!(&mut output, "Signing key is already rotated: {}", path)?
+            }
+            None => writeln!(&mut output, "Would rotate signing key: {}", path)?,
+        }
+
+        for source in &self.key_sources {
+            writeln!(&mut output, "  - {}", source)?;
+        }
+
+        if self.action == Some(KeyRotateAction::AddNew) {
+            writeln!(
+                &mut output,
+                "\nRun this command again once the repository is signed by the new key to remove the old one."
+            )?;
+        }
+
+        Ok(Some(output))
+    }
+}
+
+pub struct AuditCommand {
+    audit: Audit,
+}
+
+impl AuditCommand {
+    pub fn new(_args: cli::Audit, conf: Config) -> eyre::Result<Self> {
+        Ok(Self {
+            audit: Audit::new(
+                conf.pgp_client().as_ref(),
+                &conf.sources_dir,
+                &KeyringLocations::default(),
+                Path::new(LISTS_DIR),
+            )?,
+        })
+    }
+}
+
+impl Command for AuditCommand {
+    fn run(&mut self) -> eyre::Result<()> {
+        // Everything this command reports is gathered when it's created.
+        Ok(())
+    }
+
+    fn report(&self) -> eyre::Result<Option<String>> {
+        Ok(Some(self.audit.to_string()))
+    }
+
+    fn exit_code(&self) -> ExitCode {
+        if self.audit.findings().is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Ask the user a yes/no question, defaulting to no.
+///
+/// If stdin isn't a terminal, this returns false without asking.
+fn confirm(question: &str) -> eyre::Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    eprint!("{} [y/N] ", question);
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .wrap_err("failed reading answer from stdin")?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+pub struct KeyMigrateCommand {
+    plan: MigrationPlan,
+    yes: bool,
+}
+
+impl KeyMigrateCommand {
+    pub fn new(args: cli::KeyMigrate, conf: Config) -> eyre::Result<Self> {
+        let locations = MigrateLocations {
+            sources_dir: conf.sources_dir.clone(),
+            ..Default::default()
+        };
+
+        Ok(Self {
+            plan: MigrationPlan::new(conf.pgp_client().as_ref(), &locations)?,
+            yes: args.yes,
+        })
+    }
+}
+
+impl Command for KeyMigrateCommand {
+    fn run(&mut self) -> eyre::Result<()> {
+        self.plan.install()?;
+
+        if self.plan.can_remove_trusted()
+            && (self.yes
+                || confirm("Remove the migrated keys from the globally trusted keyrings?")?)
+        {
+            self.plan.remove_trusted()?;
+        }
+
+        Ok(())
+    }
+
+    fn report(&self) -> eyre::Result<Option<String>> {
+        Ok(Some(self.plan.to_string()))
+    }
+}
+
+pub struct RemoveCommand {
+    plan: RemovalPlan,
+}
+
+impl RemoveCommand {
+    pub fn new(args: cli::Remove, conf: Config) -> eyre::Result<Self> {
+        let locations = RemoveLocations {
+            sources_dir: conf.sources_dir,
+            auth_dir: conf.auth_dir,
+            keyring_dir: PathBuf::from("/etc/apt/keyrings"),
+        };
+
+        Ok(Self {
+            plan: RemovalPlan::new(&args.name, &locations)?,
+        })
+    }
+}
+
+impl Command for RemoveCommand {
+    fn run(&mut self) -> eyre::Result<()> {
+        self.plan.remove()
+    }
+
+    fn report(&self) -> eyre::Result<Option<String>> {
+        Ok(Some(self.plan.to_string()))
+    }
+}
+
 impl cli::Cli {
-    fn config(&self) -> Config {
-        Config {
+    fn config(&self) -> eyre::Result<Config> {
+        let config_file = match &self.config {
+            Some(path) => ConfigFile::read(path, true)?,
+            None => ConfigFile::read(Path::new(DEFAULT_CONFIG_PATH), false)?,
+        };
+
+        let client_cert = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Some(ClientCertificate {
+                cert: cert.clone(),
+                key: key.clone(),
+            }),
+            _ => config_file.client_cert,
+        };
+
+        Ok(Config {
             gpg_path: self.gpg_path.clone(),
             sources_dir: self.sources_dir.clone(),
-        }
+            default_keyservers: match (&self.default_keyserver, config_file.default_keyservers) {
+                (keyservers, _) if !keyservers.is_empty() => keyservers.clone(),
+                (_, Some(keyservers)) => keyservers,
+                (_, None) => DEFAULT_KEYSERVERS.map(String::from).to_vec(),
+            },
+            download: DownloadOptions {
+                connect_timeout: Duration::from_secs(self.connect_timeout),
+                timeout: Duration::from_secs(self.download_timeout),
+                max_size: self.max_download_size,
+                ca_cert: self.ca_cert.clone().or(config_file.ca_cert),
+                client_cert,
+                auth_headers: self.key_auth_header.clone(),
+                auth_dir: Some(self.auth_dir.clone()),
+                netrc: self.netrc.clone(),
+                proxy: ProxyConfig::default(),
+            },
+            auth_dir: self.auth_dir.clone(),
+            cache: KeyCache::new(
+                Some(self.cache_dir.clone()).filter(|_| !self.no_cache),
+                self.offline,
+            ),
+            proxy: self.proxy.clone(),
+        })
     }
 
     pub fn dispatch(&self) -> eyre::Result<Box<dyn Command>> {
-        let conf = self.config();
+        let conf = self.config()?;
 
         match &self.command {
             cli::Commands::New(args) => Ok(Box::new(NewCommand::new(args.clone(), conf)?)),
             cli::Commands::Add(args) => Ok(Box::new(AddCommand::new(args.clone(), conf)?)),
             cli::Commands::Convert(args) => Ok(Box::new(ConvertCommand::new(args.clone(), conf)?)),
+            cli::Commands::Remove(args) => Ok(Box::new(RemoveCommand::new(args.clone(), conf)?)),
+            cli::Commands::Audit(args) => Ok(Box::new(AuditCommand::new(args.clone(), conf)?)),
+            cli::Commands::Key(key_args) => match &key_args.command {
+                cli::KeyCommands::List(args) => {
+                    Ok(Box::new(KeyListCommand::new(args.clone(), conf)?))
+                }
+                cli::KeyCommands::Refresh(args) => {
+                    Ok(Box::new(KeyRefreshCommand::new(args.clone(), conf)?))
+                }
+                cli::KeyCommands::Rotate(args) => {
+                    Ok(Box::new(KeyRotateCommand::new(args.clone(), conf)?))
+                }
+                cli::KeyCommands::Migrate(args) => {
+                    Ok(Box::new(KeyMigrateCommand::new(args.clone(), conf)?))
+                }
+            },
         }
     }
 }
@@ -0,0 +1,229 @@
+//! The debctl configuration file.
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, WrapErr};
+
+use crate::error::Error;
+use crate::parse::{parse_deb822_file, Deb822Entry};
+
+/// The path of the configuration file when the user doesn't pass `--config`.
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/debctl/debctl.conf";
+
+/// The keyservers to try when neither the user nor the config file specifies any.
+pub const DEFAULT_KEYSERVERS: [&str; 2] =
+    ["hkps://keyserver.ubuntu.com", "hkps://keys.openpgp.org"];
+
+/// The field for the whitespace-separated list of keyservers to try by default.
+const DEFAULT_KEYSERVERS_FIELD: &str = "Default-Keyservers";
+
+/// The field for the path of the CA certificate bundle.
+const CA_CERT_FIELD: &str = "CA-Cert";
+
+/// The field for the path of the client certificate.
+const CLIENT_CERT_FIELD: &str = "Client-Cert";
+
+/// The field for the path of the client certificate's private key.
+const CLIENT_KEY_FIELD: &str = "Client-Key";
+
+/// A TLS client certificate and its private key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The path of the PEM-encoded certificate.
+    pub cert: PathBuf,
+
+    /// The path of the PEM-encoded PKCS #8 private key.
+    pub key: PathBuf,
+}
+
+/// The settings in the configuration file.
+///
+/// The file is a single deb822-style stanza. Relative paths are relative to the directory the file
+/// is in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigFile {
+    /// The path of a PEM-encoded CA certificate bundle to trust for downloads.
+    pub ca_cert: Option<PathBuf>,
+
+    /// The client certificate to present for downloads.
+    pub client_cert: Option<ClientCertificate>,
+
+    /// The keyservers to try, in order, when the user doesn't specify one.
+    pub default_keyservers: Option<Vec<String>>,
+}
+
+impl ConfigFile {
+    /// Read the configuration file at `path`.
+    ///
+    /// If the file doesn't exist, this returns the default configuration unless `required` is
+    /// true.
+    pub fn read(path: &Path, required: bool) -> eyre::Result<Self> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                bail!(Error::ConfigFileNotFound {
+                    path: path.to_owned()
+                })
+            }
+            Err(err) => {
+                return Err(err).wrap_err(format!("failed opening config file: {}", path.display()))
+            }
+        };
+
+        let malformed = |reason: String| Error::MalformedConfigFile {
+            path: path.to_owned(),
+            reason,
+        };
+
+        let mut stanzas = parse_deb822_file(file, true)
+            .map_err(|err| malformed(err.to_string()))?
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Deb822Entry::Entry(options) => Some(options),
+                Deb822Entry::Comment(_) => None,
+            });
+
+        let options = match (stanzas.next(), stanzas.next()) {
+            (None, _) => return Ok(Self::default()),
+            (Some(options), None) => options,
+            (Some(_), Some(_)) => bail!(malformed(String::from(
+                "It must contain a single stanza, with no blank lines between fields."
+            ))),
+        };
+
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let mut ca_cert = None;
+        let mut client_cert = None;
+        let mut client_key = None;
+        let mut default_keyservers = None;
+
+        for (name, value) in options.options() {
+            let name = name.to_deb822();
+
+            if name.eq_ignore_ascii_case(DEFAULT_KEYSERVERS_FIELD) {
+                default_keyservers = Some(
+                    value
+                        .to_deb822()
+                        .split_whitespace()
+                        .map(String::from)
+                        .collect(),
+                );
+                continue;
+            }
+
+            let value = Some(base_dir.join(value.to_deb822().as_ref()));
+
+            if name.eq_ignore_ascii_case(CA_CERT_FIELD) {
+                ca_cert = value;
+            } else if name.eq_ignore_ascii_case(CLIENT_CERT_FIELD) {
+                client_cert = value;
+            } else if name.eq_ignore_ascii_case(CLIENT_KEY_FIELD) {
+                client_key = value;
+            } else {
+                bail!(malformed(format!("This is not a known field: `{name}`.")));
+            }
+        }
+
+        let client_cert = match (client_cert, client_key) {
+            (Some(cert), Some(key)) => Some(ClientCertificate { cert, key }),
+            (None, None) => None,
+            _ => bail!(malformed(format!(
+                "The `{CLIENT_CERT_FIELD}` and `{CLIENT_KEY_FIELD}` fields must be set together."
+            ))),
+        };
+
+        Ok(Self {
+            ca_cert,
+            client_cert,
+            default_keyservers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use xpct::{be_err, be_ok, equal, expect};
+
+    use super::*;
+
+    #[test]
+    fn missing_config_file_uses_defaults_unless_required() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("debctl.conf");
+
+        expect!(ConfigFile::read(&path, false))
+            .to(be_ok())
+            .to(equal(ConfigFile::default()));
+
+        expect!(ConfigFile::read(&path, true))
+            .to(be_err())
+            .map(|err| err.downcast::<Error>().ok())
+            .to(equal(Some(Error::ConfigFileNotFound { path })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_certificate_paths_relative_to_config_file() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("debctl.conf");
+
+        fs::write(
+            &path,
+            "# Trust the corporate CA.\nCA-Cert: /etc/ssl/corp-ca.pem\nclient-cert: client.pem\nClient-Key: client.key\n",
+        )?;
+
+        expect!(ConfigFile::read(&path, true))
+            .to(be_ok())
+            .to(equal(ConfigFile {
+                ca_cert: Some(PathBuf::from("/etc/ssl/corp-ca.pem")),
+                client_cert: Some(ClientCertificate {
+                    cert: temp_dir.path().join("client.pem"),
+                    key: temp_dir.path().join("client.key"),
+                }),
+                default_keyservers: None,
+            }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_default_keyservers_in_order() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("debctl.conf");
+
+        fs::write(
+            &path,
+            "Default-Keyservers: hkps://keys.example.com\n hkp://mirror.example.com:11371\n",
+        )?;
+
+        expect!(ConfigFile::read(&path, true))
+            .to(be_ok())
+            .map(|config| config.default_keyservers)
+            .to(equal(Some(vec![
+                String::from("hkps://keys.example.com"),
+                String::from("hkp://mirror.example.com:11371"),
+            ])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn client_certificate_requires_key() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("debctl.conf");
+
+        fs::write(&path, "Client-Cert: client.pem\n")?;
+
+        expect!(ConfigFile::read(&path, true)).to(be_err());
+
+        Ok(())
+    }
+}
@@ -6,11 +6,16 @@ use std::path::{Path, PathBuf};
 use eyre::{bail, WrapErr};
 
 use crate::args::{AddArgs, NewArgs, OverwriteAction};
+use crate::cache::KeyCache;
 use crate::error::Error;
 use crate::file::SourceFile;
-use crate::key::{KeyDest, KeySource, SigningKey};
-use crate::option::OptionMap;
-use crate::pgp::PgpClient;
+use crate::key::{
+    fetch_keys, install_key, key_to_value, key_weaknesses, KeyDest, KeyInstallAction, KeySource,
+    KeyWeakness, SigningKey, MINIMAL_KEY_MODE,
+};
+use crate::net::HttpClient;
+use crate::option::{KnownOptionName, OptionMap};
+use crate::pgp::{KeyEncoding, KeyExportMode, PgpClient};
 
 /// A plan for how we will install the source entry.
 ///
@@ -80,13 +85,38 @@ impl InstallPlan {
 #[derive(Debug)]
 pub struct SourceEntry {
     options: OptionMap,
-    key: Option<KeySource>,
+    keys: Vec<KeySource>,
+    strict: bool,
+    weaknesses: Vec<KeyWeakness>,
 }
 
 impl SourceEntry {
     /// Create a new instance.
-    pub fn new(options: OptionMap, key: Option<KeySource>) -> Self {
-        Self { options, key }
+    ///
+    /// The signing `keys` are combined into a single keyring when installed.
+    pub fn new(options: OptionMap, keys: Vec<KeySource>) -> Self {
+        Self {
+            options,
+            keys,
+            strict: false,
+            weaknesses: Vec::new(),
+        }
+    }
+
+    /// Refuse to install signing keys that apt would consider weak.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The locations of the signing keys for this source entry.
+    pub fn keys(&self) -> &[KeySource] {
+        &self.keys
+    }
+
+    /// The reasons apt would consider the installed signing keys weak.
+    pub fn key_weaknesses(&self) -> &[KeyWeakness] {
+        &self.weaknesses
     }
 
     /// A plan for what installing this entry will do.
@@ -96,36 +126,96 @@ impl SourceEntry {
 
     /// Construct an instance from the CLI `args`.
     pub fn from_new(args: &NewArgs) -> eyre::Result<Self> {
-        Ok(Self::new(args.options(), args.key().source.clone()))
+        Ok(Self::new(args.options(), args.key().sources.clone()).strict(args.key().strict))
     }
 
     /// Construct an instance from the CLI `args`.
     pub fn from_add(args: &AddArgs) -> eyre::Result<Self> {
-        Ok(Self::new(args.options()?, args.key().source.to_owned()))
+        Ok(Self::new(args.options()?, args.key().sources.to_owned()).strict(args.key().strict))
     }
 
-    /// Install the key for this source entry.
-    pub fn install_key(&mut self, client: &dyn PgpClient, dest: &KeyDest) -> eyre::Result<()> {
-        if let Some(key_location) = &self.key {
-            let key = match dest {
-                KeyDest::File { path } => {
-                    key_location
-                        .install(client, path)
-                        .wrap_err("failed installing signing key to file")?;
+    /// Install the signing keys for this source entry.
+    ///
+    /// This returns what happened to the key file, if the keys were installed to a file. If apt
+    /// would consider the keys weak, this fails in strict mode and otherwise records why.
+    pub fn install_key(
+        &mut self,
+        client: &dyn PgpClient,
+        http: &dyn HttpClient,
+        cache: &KeyCache,
+        dest: &KeyDest,
+    ) -> eyre::Result<Option<KeyInstallAction>> {
+        if self.keys.is_empty() {
+            return Ok(None);
+        }
+
+        let (encoding, mode) = match dest {
+            KeyDest::File(file_dest) => (file_dest.encoding, file_dest.mode),
+            KeyDest::Inline { mode } => (KeyEncoding::Armored, *mode),
+        };
+
+        let key = fetch_keys(&self.keys, client, http, cache, encoding, mode)?;
+
+        self.weaknesses = key_weaknesses(client, &key)?;
+
+        if self.strict && !self.weaknesses.is_empty() {
+            bail!(Error::WeakSigningKey {
+                weaknesses: self.weaknesses.iter().map(ToString::to_string).collect(),
+            });
+        }
 
-                    SigningKey::File { path: path.clone() }
+        let (key, action) = match dest {
+            KeyDest::File(file_dest) => {
+                let action = install_key(&key, client, file_dest)
+                    .wrap_err("failed installing signing key to file")?;
+
+                // If we reused an existing key file, the source entry needs to point to it.
+                let path = match &action {
+                    KeyInstallAction::Reuse {
+                        path: existing_path,
+                    } => existing_path.clone(),
+                    _ => file_dest.path.clone(),
+                };
+
+                // Record where the keys came from so they can be refreshed later. If any of them
+                // can't be fetched again, refreshing would drop that key, so we record nothing.
+                if let Some(tokens) = self
+                    .keys
+                    .iter()
+                    .map(KeySource::to_token)
+                    .collect::<Option<Vec<_>>>()
+                {
+                    self.options.insert(KnownOptionName::KeySource, tokens);
+
+                    // Refreshing the keys needs to export them the same way.
+                    if file_dest.mode == KeyExportMode::Minimal {
+                        self.options
+                            .insert(KnownOptionName::KeyMode, MINIMAL_KEY_MODE);
+                    }
                 }
-                KeyDest::Inline => SigningKey::Inline {
-                    value: key_location
-                        .to_value(client)
-                        .wrap_err("failed installing inline signing key")?,
+
+                let key = if file_dest.fingerprints.is_empty() {
+                    SigningKey::File { path }
+                } else {
+                    SigningKey::Fingerprints {
+                        path,
+                        fingerprints: file_dest.fingerprints.clone(),
+                    }
+                };
+
+                (key, Some(action))
+            }
+            KeyDest::Inline { .. } => (
+                SigningKey::Inline {
+                    value: key_to_value(&key).wrap_err("failed installing inline signing key")?,
                 },
-            };
+                None,
+            ),
+        };
 
-            self.options.insert_key(key)?;
-        }
+        self.options.insert_key(key)?;
 
-        Ok(())
+        Ok(action)
     }
 
     /// Open the repo source file.
@@ -164,13 +254,10 @@ impl SourceEntry {
         }
     }
 
-    fn write_options(&self, mut dest: impl Write) -> eyre::Result<()> {
-        for (key, value) in self.options.options() {
-            writeln!(&mut dest, "{}: {}", key.to_deb822(), value.to_deb822())
-                .wrap_err("failed writing option to source file")?;
-        }
-
-        Ok(())
+    fn write_options(&self, dest: impl Write) -> eyre::Result<()> {
+        self.options
+            .write_deb822(dest)
+            .wrap_err("failed writing option to source file")
     }
 
     /// Install this source entry to the given file in deb822 format.
@@ -216,10 +303,12 @@ mod tests {
     use rstest::*;
     use xpct::{be_err, be_existing_file, be_ok, equal, expect, have_len};
 
+    use crate::args::KeyArgOptions;
     use crate::cli;
     use crate::error::Error;
     use crate::file::{SourceFile, SourceFileKind, SourceFilePath};
-    use crate::types::SourceType;
+    use crate::pgp::GnupgClient;
+    use crate::types::{KeyFormat, SourceType};
 
     use super::*;
 
@@ -228,8 +317,18 @@ mod tests {
     }
 
     impl EntryParams {
+        pub fn parse(&self) -> eyre::Result<NewArgs> {
+            let options = KeyArgOptions {
+                default_keyservers: &[],
+                max_stdin_size: 0,
+                client: &GnupgClient::new("gpg"),
+            };
+
+            NewArgs::from_cli(self.args.clone(), &options)
+        }
+
         pub fn install(&self, file: &SourceFile, action: OverwriteAction) -> eyre::Result<()> {
-            SourceEntry::from_new(&NewArgs::from_cli(self.args.clone())?)?.install(file, action)
+            SourceEntry::from_new(&self.parse()?)?.install(file, action)
         }
     }
 
@@ -245,15 +344,33 @@ mod tests {
                 kind: vec![SourceType::Deb],
                 key: cli::SigningKeyArgs {
                     location: cli::KeySourceArgs {
-                        key: None,
+                        key: Vec::new(),
                         force_no_key: true,
                     },
-                    keyserver: None,
+                    keyserver: cli::KeyserverArgs {
+                        keyserver: Vec::new(),
+                        allow_long_key_id: false,
+                    },
+                    fetch: cli::KeyFetchArgs {
+                        package_keyring: None,
+                        allow_insecure_key_url: false,
+                    },
+                    minimal_key: false,
+                    no_minimal_key: false,
+                    key_fingerprint: Vec::new(),
+                    overwrite_key: false,
+                    reuse_existing_key: false,
+                    strict: false,
                     destination: cli::KeyDestinationArgs {
                         key_path: None,
                         inline_key: false,
+                        key_format: None,
                     },
                 },
+                credentials: cli::CredentialsArgs {
+                    username: None,
+                    password_file: None,
+                },
                 arch: Vec::new(),
                 lang: Vec::new(),
                 option: Vec::new(),
@@ -267,6 +384,27 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("/etc/apt/keyrings/myrepo.asc", KeyFormat::Binary, false)]
+    #[case("/etc/apt/keyrings/myrepo.gpg", KeyFormat::Armored, false)]
+    #[case("/etc/apt/keyrings/myrepo.asc", KeyFormat::Armored, true)]
+    #[case("/etc/apt/keyrings/myrepo.key", KeyFormat::Armored, true)]
+    fn key_format_must_match_key_path_extension(
+        mut entry: EntryParams,
+        #[case] key_path: &str,
+        #[case] key_format: KeyFormat,
+        #[case] is_valid: bool,
+    ) {
+        entry.args.key.location = cli::KeySourceArgs {
+            key: vec!["D9C5A07BD817FAEF313D6434B2FF242E7718CD4C".into()],
+            force_no_key: false,
+        };
+        entry.args.key.destination.key_path = Some(key_path.into());
+        entry.args.key.destination.key_format = Some(key_format);
+
+        expect!(entry.parse().is_ok()).to(equal(is_valid));
+    }
+
     #[rstest]
     fn installing_fails_when_output_file_already_exists(entry: EntryParams) -> eyre::Result<()> {
         let temp_file = tempfile::NamedTempFile::new()?;
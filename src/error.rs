@@ -1,7 +1,48 @@
+use std::fmt::Write;
 use std::path::PathBuf;
 
 use thiserror::Error;
 
+/// A failed attempt to fetch a key from a keyserver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyserverFailure {
+    /// The keyserver we tried.
+    pub keyserver: String,
+
+    /// Why fetching the key from this keyserver failed.
+    pub reason: String,
+}
+
+/// Format the list of keyservers we tried and why each one failed.
+fn format_keyserver_failures(failures: &[KeyserverFailure]) -> String {
+    if failures.is_empty() {
+        return String::from("No keyservers were configured.");
+    }
+
+    let mut output = String::from("Tried these keyservers in order:\n");
+
+    for failure in failures {
+        write!(&mut output, "\n* {}", failure.keyserver).unwrap();
+
+        for line in failure.reason.lines() {
+            write!(&mut output, "\n  {}", line).unwrap();
+        }
+    }
+
+    output
+}
+
+/// Format a list of items as a bulleted list.
+fn format_list(items: &[String]) -> String {
+    let mut output = String::new();
+
+    for item in items {
+        write!(&mut output, "\n* {}", item).unwrap();
+    }
+
+    output
+}
+
 /// A error type for user-facing errors.
 ///
 /// This type represents errors expected in common usage of the program that should trigger a
@@ -23,17 +64,110 @@ pub enum Error {
     #[error("This one-line-style entry is malformed.\n\n{reason}")]
     MalformedOneLineEntry { reason: String },
 
+    #[error("This deb822-style source entry is malformed.\n\n{reason}")]
+    MalformedDeb822Entry { reason: String },
+
     #[error("This key is not a valid URL or file path: `{path}`.")]
     InvalidKeyLocation { path: String },
 
+    #[error("This `data:` URL is malformed: {reason}.")]
+    MalformedDataUrl { reason: String },
+
+    #[error("This signing key URL doesn't use HTTPS: `{url}`.\n\nAnyone on the network path could replace a key downloaded over plain HTTP with their own, which would let them sign packages for this repository. Use an `https://` URL if the server supports it, or pass `--allow-insecure-key-url` if you can verify the key some other way.")]
+    InsecureKeyUrl { url: String },
+
     #[error("Failed to download key from URL: `{url}`.\n\n{reason}")]
     KeyDownloadFailed { url: String, reason: String },
 
+    #[error("This `--key-auth-header` is not a valid HTTP header in `Name: value` format.\n\nThe header isn't shown here because it may contain a secret.")]
+    MalformedAuthHeader,
+
+    #[error("These repository credentials can't be written to apt's auth.conf.d.\n\n{reason}")]
+    InvalidCredentials { reason: String },
+
+    #[error("This credentials file is malformed: `{path}`.\n\n{reason}")]
+    MalformedAuthFile { path: PathBuf, reason: String },
+
+    #[error("This is not a valid proxy URL: `{proxy}`.\n\nIt must be a URL like `http://proxy.example.com:3128`, or `DIRECT` to not use a proxy.")]
+    InvalidProxy { proxy: String },
+
+    #[error("This signing key isn't in the cache, and `--offline` was passed: `{key}`.\n\nRun debctl once with network access to cache it, or copy a cache from a machine that has it to `--cache-dir`.")]
+    NotCached { key: String },
+
+    #[error("The cached key doesn't match the fingerprint you asked for: `{id}`.\n\nThe cache file may be corrupt or have been tampered with: `{path}`.")]
+    CachedKeyMismatch { id: String, path: PathBuf },
+
+    #[error("Refused to follow a redirect from HTTPS to an insecure URL: `{from}` redirected to `{to}`.\n\nAnyone on the network path could tamper with a download that isn't over HTTPS.")]
+    InsecureRedirect { from: String, to: String },
+
+    #[error("Too many redirects when downloading: `{url}`.")]
+    TooManyRedirects { url: String },
+
+    #[error("This download is larger than the limit of {max_size} bytes: `{url}`.\n\nYou can raise the limit with `--max-download-size`.")]
+    DownloadTooLarge { url: String, max_size: u64 },
+
+    #[error("The key piped to stdin is larger than the limit of {max_size} bytes.\n\nYou can raise the limit with `--max-download-size`.")]
+    StdinKeyTooLarge { max_size: u64 },
+
     #[error("You cannot pass the `Signed-By` option without also passing `--force-no-key`.\n\nYou should typically use `--key` to specify the signing key.")]
     ConflictingKeyLocations,
 
-    #[error("Failed to fetch key from the keyserver: `{id}`.\n\n{reason}")]
-    KeyserverFetchFailed { id: String, reason: String },
+    #[error("Failed to fetch key from the keyservers: `{id}`.\n\n{}", format_keyserver_failures(.failures))]
+    KeyserverFetchFailed {
+        id: String,
+        failures: Vec<KeyserverFailure>,
+    },
+
+    #[error("This key ID is too short to be secure: `{id}`.\n\nShort key IDs are easy to forge. Use the key's full fingerprint instead.")]
+    ShortKeyId { id: String },
+
+    #[error("This is a long key ID rather than a full fingerprint: `{id}`.\n\nYou should use the key's full fingerprint, or pass `--allow-long-key-id` to accept it anyway.")]
+    LongKeyId { id: String },
+
+    #[error("This is not a valid key fingerprint: `{id}`.\n\nFingerprints are 40 hex characters for v4 keys or 64 hex characters for v6 keys.")]
+    InvalidKeyId { id: String },
+
+    #[error("The keyserver returned a key that doesn't match the fingerprint you asked for: `{id}`.\n\nThe returned key has these fingerprints:\n{}", format_list(.fingerprints))]
+    KeyFingerprintMismatch {
+        id: String,
+        fingerprints: Vec<String>,
+    },
+
+    #[error(
+        "This fingerprint doesn't match any key or subkey in the signing key: `{fingerprint}`."
+    )]
+    FingerprintNotInKey { fingerprint: String },
+
+    #[error("apt considers this signing key weak:\n{}\n\nYou can install it anyway by leaving out `--strict`.", format_list(.weaknesses))]
+    WeakSigningKey { weaknesses: Vec<String> },
+
+    #[error("There is already a different signing key installed here: `{path}`.\n\nIt may be used by other sources. You can either:\n* Overwrite it with `--overwrite-key`\n* Install the key somewhere else with `--key-path`")]
+    KeyFileAlreadyExists { path: PathBuf },
+
+    #[error("This is not a valid recorded key source: `{token}`.")]
+    MalformedKeySource { token: String },
+
+    #[error("The primary keys changed when refreshing this keyring: `{path}`.\n\nThis could mean the repository replaced its signing key, or that the place the key was fetched from was compromised. The keyring was not updated.\n\nThe installed keyring contains:{}\n\nThe refreshed keyring contains:{}", format_list(.old), format_list(.new))]
+    KeyFingerprintChanged {
+        path: PathBuf,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+
+    #[error("There are no signing keys to refresh for this source: `{name}`.\n\nOnly signing keys that debctl installed to a separate file can be refreshed.")]
+    NoKeysToRefresh { name: String },
+
+    #[error("This source doesn't use a signing key installed to a separate file: `{name}`.\n\nOnly keys referenced by the `Signed-By` option of a deb822-style source can be rotated.")]
+    NoKeyToRotate { name: String },
+
+    #[error("This source uses more than one signing key file: `{name}`.\n\nThe source uses these key files:{}", format_list(.paths))]
+    MultipleKeyFiles { name: String, paths: Vec<String> },
+
+    #[error("This signing key file is also used by other sources: `{path}`.\n\nRotating it would change the keys those sources trust too. These source files use it:{}", format_list(.sources))]
+    SharedKeyFile { path: PathBuf, sources: Vec<String> },
+
+    #[error("There is no source file here: `{path}`.")]
+    SourceFileNotFound { path: PathBuf },
 
     #[error("Could not find GnuPG command on your `PATH`: `{path}`\n\nIs GnuPG installed?")]
     GnupgNotFound { path: String },
@@ -41,6 +175,40 @@ pub enum Error {
     #[error("This is not a valid PGP key: `{key}`.")]
     NotPgpKey { key: String },
 
+    #[error("This is not a valid .deb package: {reason}.")]
+    MalformedPackage { reason: String },
+
+    #[error("This .deb package uses a compression format that isn't supported: `{member}`.\n\nPackages compressed with gzip, xz, and zstd are supported.")]
+    UnsupportedPackageCompression { member: String },
+
+    #[error("This package doesn't contain any keyrings: `{package}`.\n\nKeyrings are looked for in `/usr/share/keyrings` and `/etc/apt/trusted.gpg.d`.")]
+    NoKeyringInPackage { package: String },
+
+    #[error("This package contains more than one keyring: `{package}`.\n\nUse `--package-keyring` to choose one of these keyrings:{}", format_list(.keyrings))]
+    MultipleKeyringsInPackage {
+        package: String,
+        keyrings: Vec<String>,
+    },
+
+    #[error("This package doesn't contain the keyring `{keyring}`: `{package}`.\n\nThe package contains these keyrings:{}", format_list(.keyrings))]
+    KeyringNotInPackage {
+        package: String,
+        keyring: String,
+        keyrings: Vec<String>,
+    },
+
+    #[error("There is no config file here: `{path}`.")]
+    ConfigFileNotFound { path: PathBuf },
+
+    #[error("This config file is malformed: `{path}`.\n\n{reason}")]
+    MalformedConfigFile { path: PathBuf, reason: String },
+
+    #[error("This is not a valid PEM-encoded certificate: `{path}`.\n\n{reason}")]
+    InvalidCertificate { path: PathBuf, reason: String },
+
+    #[error("This client certificate or its private key is invalid: `{cert}`.\n\nThe certificate and the private key must both be PEM-encoded, and the private key must be in PKCS #8 format.\n\n{reason}")]
+    InvalidClientCertificate { cert: PathBuf, reason: String },
+
     #[error("There is no source file here: `{path}`.")]
     ConvertInFileNotFound { path: PathBuf },
 
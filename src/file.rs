@@ -1,8 +1,15 @@
 use std::{
     borrow::Cow,
+    fs,
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
 };
 
+use eyre::{bail, WrapErr};
+
+use crate::error::Error;
+
 /// The path of a repo source file.
 #[derive(Debug, Clone)]
 pub enum SourceFilePath {
@@ -50,3 +57,40 @@ impl SourceFile {
         }
     }
 }
+
+/// Replace the file at `path` with `contents` atomically.
+///
+/// The contents are written to a temporary file in the same directory, which is then renamed over
+/// the original, so apt never sees a partially written file.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> eyre::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut temp_file = match tempfile::Builder::new().prefix(".debctl-").tempfile_in(dir) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => bail!(Error::PermissionDenied),
+        Err(err) => return Err(err).wrap_err("failed creating temporary file"),
+    };
+
+    temp_file
+        .write_all(contents)
+        .wrap_err("failed writing temporary file")?;
+
+    temp_file
+        .as_file()
+        .sync_all()
+        .wrap_err("failed writing temporary file")?;
+
+    // Temporary files are only readable by their owner, but apt reads source files and keyrings as
+    // an unprivileged user.
+    fs::set_permissions(temp_file.path(), fs::Permissions::from_mode(0o644))
+        .wrap_err("failed setting permissions of temporary file")?;
+
+    temp_file
+        .persist(path)
+        .wrap_err("failed renaming temporary file")?;
+
+    Ok(())
+}
@@ -0,0 +1,353 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use eyre::{bail, WrapErr};
+
+use crate::error::Error;
+use crate::key::KEY_FILE_EXTENSIONS;
+use crate::pgp::{Fingerprint, KeyEncoding, KeyInfo, PgpClient};
+use crate::sources::{InstalledEntry, SignedBy};
+
+/// The locations apt keyrings are installed to.
+#[derive(Debug, Clone)]
+pub struct KeyringLocations {
+    /// Directories of keyrings that are only trusted by the sources which reference them.
+    pub dirs: Vec<PathBuf>,
+
+    /// The directory of keyrings that apt trusts for every source.
+    pub trusted_dir: PathBuf,
+
+    /// The legacy keyring file that apt trusts for every source.
+    pub trusted_file: PathBuf,
+}
+
+impl Default for KeyringLocations {
+    fn default() -> Self {
+        Self {
+            dirs: vec![
+                PathBuf::from("/etc/apt/keyrings"),
+                PathBuf::from("/usr/share/keyrings"),
+            ],
+            trusted_dir: PathBuf::from("/etc/apt/trusted.gpg.d"),
+            trusted_file: PathBuf::from("/etc/apt/trusted.gpg"),
+        }
+    }
+}
+
+/// A keyring file installed on the system.
+#[derive(Debug, Clone)]
+pub struct InstalledKeyring {
+    /// The path of the keyring file.
+    pub path: PathBuf,
+
+    /// Whether apt trusts this keyring for every source.
+    pub trusted: bool,
+
+    /// The keys in this keyring.
+    pub keys: Vec<KeyInfo>,
+}
+
+impl InstalledKeyring {
+    /// Return whether this keyring contains a key or subkey with this fingerprint.
+    pub fn contains(&self, fingerprint: &Fingerprint) -> bool {
+        self.keys.iter().any(|key| {
+            &key.fingerprint == fingerprint
+                || key.subkeys.iter().any(|subkey| subkey == fingerprint)
+        })
+    }
+
+    /// Return whether a source entry with this `Signed-By` option uses this keyring.
+    pub fn is_used_by(&self, signed_by: &[SignedBy]) -> bool {
+        signed_by.iter().any(|key| match key {
+            SignedBy::Path(path) => is_same_file(path, &self.path),
+            // Fingerprints without a keyring path refer to the globally trusted keyrings.
+            SignedBy::Fingerprint(fingerprint) => self.trusted && self.contains(fingerprint),
+            SignedBy::Inline => false,
+        })
+    }
+}
+
+/// Return whether these two paths refer to the same file.
+fn is_same_file(first: &Path, second: &Path) -> bool {
+    if first == second {
+        return true;
+    }
+
+    match (first.canonicalize(), second.canonicalize()) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+/// Return the paths of the key files in `dir`.
+fn key_file_paths(dir: &Path) -> eyre::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => bail!(Error::PermissionDenied),
+        Err(err) => return Err(err).wrap_err("failed reading keyring directory"),
+    };
+
+    let mut paths = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err("failed reading keyring directory")?;
+
+    paths.retain(|path| {
+        path.is_file()
+            && path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| KEY_FILE_EXTENSIONS.contains(&extension))
+    });
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Read the keyring at `path`.
+///
+/// This returns `None` if the file isn't a valid PGP key.
+fn read_keyring(
+    client: &dyn PgpClient,
+    path: &Path,
+    trusted: bool,
+) -> eyre::Result<Option<InstalledKeyring>> {
+    let key = match client.read_key(path, KeyEncoding::Binary) {
+        Ok(key) => key,
+        Err(err) => match err.downcast_ref::<Error>() {
+            Some(Error::NotPgpKey { .. }) => return Ok(None),
+            _ => return Err(err),
+        },
+    };
+
+    Ok(Some(InstalledKeyring {
+        path: path.to_owned(),
+        trusted,
+        keys: client.key_info(&key)?,
+    }))
+}
+
+/// Return all the keyrings installed in `locations`.
+///
+/// Files which aren't valid PGP keys are skipped.
+pub fn installed_keyrings(
+    client: &dyn PgpClient,
+    locations: &KeyringLocations,
+) -> eyre::Result<Vec<InstalledKeyring>> {
+    let mut paths = Vec::new();
+
+    for dir in &locations.dirs {
+        paths.extend(key_file_paths(dir)?.into_iter().map(|path| (path, false)));
+    }
+
+    if locations.trusted_file.is_file() {
+        paths.push((locations.trusted_file.clone(), true));
+    }
+
+    paths.extend(
+        key_file_paths(&locations.trusted_dir)?
+            .into_iter()
+            .map(|path| (path, true)),
+    );
+
+    let mut keyrings = Vec::new();
+
+    for (path, trusted) in paths {
+        let keyring = read_keyring(client, &path, trusted)
+            .wrap_err_with(|| format!("failed reading keyring: {}", path.display()))?;
+
+        keyrings.extend(keyring);
+    }
+
+    Ok(keyrings)
+}
+
+/// Format a Unix timestamp as a `YYYY-MM-DD` date in UTC.
+///
+/// Timestamps past the year 9999 are printed as-is.
+fn format_date(timestamp: u64) -> String {
+    // This is the `civil_from_days` algorithm from
+    // https://howardhinnant.github.io/date_algorithms.html, which counts days from 0000-03-01 so
+    // leap days fall at the end of each year.
+    let days = timestamp / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    if year > 9999 {
+        return timestamp.to_string();
+    }
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// A keyring in a [`KeyringListing`].
+#[derive(Debug)]
+struct ListedKeyring {
+    keyring: InstalledKeyring,
+
+    /// The source files with entries that use this keyring.
+    used_by: Vec<PathBuf>,
+}
+
+/// A listing of the installed keyrings and which source entries use them.
+#[derive(Debug)]
+pub struct KeyringListing {
+    keyrings: Vec<ListedKeyring>,
+
+    /// The current time, in seconds since the Unix epoch.
+    now: u64,
+}
+
+impl KeyringListing {
+    /// Cross-reference the `keyrings` with the source `entries` that use them.
+    pub fn new(keyrings: Vec<InstalledKeyring>, entries: &[InstalledEntry]) -> Self {
+        let keyrings = keyrings
+            .into_iter()
+            .map(|keyring| {
+                let mut used_by = entries
+                    .iter()
+                    .filter(|entry| keyring.is_used_by(&entry.signed_by()))
+                    .map(|entry| entry.path.clone())
+                    .collect::<Vec<_>>();
+
+                // A source file with several entries which use this keyring is only listed once.
+                used_by.sort();
+                used_by.dedup();
+
+                ListedKeyring { keyring, used_by }
+            })
+            .collect();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        Self { keyrings, now }
+    }
+}
+
+impl fmt::Display for KeyringListing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.keyrings.is_empty() {
+            return writeln!(f, "No keyrings are installed.");
+        }
+
+        for (index, listed) in self.keyrings.iter().enumerate() {
+            let keyring = &listed.keyring;
+
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", keyring.path.display())?;
+
+            if keyring.trusted {
+                write!(f, " [globally trusted]")?;
+            } else if listed.used_by.is_empty() {
+                write!(f, " [orphaned]")?;
+            }
+
+            writeln!(f)?;
+
+            for key in &keyring.keys {
+                writeln!(f, "  Key: {}", key.fingerprint)?;
+
+                for user_id in &key.user_ids {
+                    writeln!(f, "    {}", user_id)?;
+                }
+
+                match key.expires {
+                    Some(expires) if expires <= self.now => {
+                        writeln!(f, "    Expired: {}", format_date(expires))?
+                    }
+                    Some(expires) => writeln!(f, "    Expires: {}", format_date(expires))?,
+                    None => writeln!(f, "    Expires: never")?,
+                }
+            }
+
+            if keyring.trusted {
+                writeln!(
+                    f,
+                    "  Trusted for every source, including those without a Signed-By option"
+                )?;
+            } else if listed.used_by.is_empty() {
+                writeln!(f, "  Not used by any source")?;
+            }
+
+            if !listed.used_by.is_empty() {
+                writeln!(f, "  Used by:")?;
+
+                for path in &listed.used_by {
+                    writeln!(f, "    - {}", path.display())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use xpct::{equal, expect};
+
+    use super::*;
+
+    #[rstest]
+    #[case(0, "1970-01-01")]
+    #[case(951_782_400, "2000-02-29")]
+    #[case(1_704_067_199, "2023-12-31")]
+    #[case(4_102_444_800, "2100-01-01")]
+    #[case(4_107_542_399, "2100-02-28")]
+    #[case(4_107_542_400, "2100-03-01")]
+    #[case(253_402_300_799, "9999-12-31")]
+    #[case(253_402_300_800, "253402300800")]
+    #[case(u64::MAX, "18446744073709551615")]
+    fn formats_dates(#[case] timestamp: u64, #[case] date: &str) {
+        expect!(format_date(timestamp)).to(equal(date.to_string()));
+    }
+
+    #[test]
+    fn keyring_is_used_by_source_which_references_it() {
+        let fingerprint = Fingerprint::new("D9C5A07BD817FAEF313D6434B2FF242E7718CD4C".into());
+
+        let keyring = InstalledKeyring {
+            path: PathBuf::from("/etc/apt/keyrings/repo.gpg"),
+            trusted: false,
+            keys: vec![KeyInfo {
+                fingerprint: fingerprint.clone(),
+                subkeys: Vec::new(),
+                user_ids: Vec::new(),
+                expires: None,
+            }],
+        };
+
+        expect!(keyring.is_used_by(&[SignedBy::Path("/etc/apt/keyrings/repo.gpg".into())]))
+            .to(equal(true));
+
+        expect!(keyring.is_used_by(&[SignedBy::Path("/etc/apt/keyrings/other.gpg".into())]))
+            .to(equal(false));
+
+        // Bare fingerprints only refer to globally trusted keyrings.
+        expect!(keyring.is_used_by(&[SignedBy::Fingerprint(fingerprint)])).to(equal(false));
+    }
+}
@@ -1,17 +1,29 @@
 #![forbid(unsafe_code)]
 
 mod args;
+mod audit;
+mod auth;
+mod cache;
 mod cli;
 mod codename;
 mod command;
+mod config;
 mod convert;
 mod entry;
 mod error;
 mod file;
 mod key;
+mod keyring;
+mod lists;
+mod migrate;
+mod net;
 mod option;
+mod package;
 mod parse;
 mod pgp;
+mod proxy;
+mod remove;
+mod sources;
 mod stdio;
 mod types;
 
@@ -21,7 +33,7 @@ use clap::Parser;
 use cli::Cli;
 use error::Error;
 
-fn run() -> eyre::Result<()> {
+fn run() -> eyre::Result<ExitCode> {
     let cli = Cli::parse();
 
     let mut command = cli.dispatch()?;
@@ -34,21 +46,22 @@ fn run() -> eyre::Result<()> {
         eprintln!("{}", report);
     }
 
-    Ok(())
+    Ok(command.exit_code())
 }
 
 fn main() -> eyre::Result<ExitCode> {
     color_eyre::install()?;
 
-    if let Err(err) = run() {
-        // User-facing errors should not show a stack trace.
-        if let Some(user_err) = err.downcast_ref::<Error>() {
-            eprintln!("{}", user_err);
-            return Ok(ExitCode::FAILURE);
-        }
+    match run() {
+        Ok(code) => Ok(code),
+        Err(err) => {
+            // User-facing errors should not show a stack trace.
+            if let Some(user_err) = err.downcast_ref::<Error>() {
+                eprintln!("{}", user_err);
+                return Ok(ExitCode::FAILURE);
+            }
 
-        return Err(err);
+            Err(err)
+        }
     }
-
-    Ok(ExitCode::SUCCESS)
 }
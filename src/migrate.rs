@@ -0,0 +1,714 @@
+//! Migrating signing keys out of the globally trusted keyrings.
+//!
+//! Keys installed with the deprecated `apt-key` live in keyrings that apt trusts for every source.
+//! Migrating them moves each key to its own key file and points the sources it signs at that file
+//! with the `Signed-By` option.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, WrapErr};
+
+use crate::error::Error;
+use crate::file::{write_atomically, SourceFileKind};
+use crate::key::{ensure_dir_exists, has_same_keys};
+use crate::keyring::{installed_keyrings, KeyringLocations};
+use crate::lists::{SignerFinder, LISTS_DIR};
+use crate::option::{KnownOptionName, OptionMap};
+use crate::parse::{parse_deb822_file, parse_line_entry, Deb822Entry};
+use crate::pgp::{Fingerprint, Key, KeyEncoding, KeyExportMode, KeyInfo, PgpClient};
+use crate::sources::{source_file_paths, update_deb822_contents};
+use crate::types::KeyFormat;
+
+/// The locations a migration reads from and writes to.
+#[derive(Debug, Clone)]
+pub struct MigrateLocations {
+    /// The path of the APT sources directory.
+    pub sources_dir: PathBuf,
+
+    /// The locations of the installed keyrings.
+    pub keyrings: KeyringLocations,
+
+    /// The directory apt downloads repository metadata to.
+    pub lists_dir: PathBuf,
+
+    /// The directory to install the migrated keys to.
+    pub dest_dir: PathBuf,
+}
+
+impl Default for MigrateLocations {
+    fn default() -> Self {
+        Self {
+            sources_dir: PathBuf::from("/etc/apt/sources.list.d"),
+            keyrings: KeyringLocations::default(),
+            lists_dir: PathBuf::from(LISTS_DIR),
+            dest_dir: PathBuf::from("/etc/apt/keyrings"),
+        }
+    }
+}
+
+/// Add an option to a one-line-style entry, keeping the rest of the line as it was.
+fn add_line_option(line: &str, name: &str, value: &str) -> String {
+    let indent_len = line.len() - line.trim_start().len();
+    let (indent, entry) = line.split_at(indent_len);
+
+    let (source_type, rest) = entry.split_once(char::is_whitespace).unwrap_or((entry, ""));
+    let rest = rest.trim_start();
+
+    match rest.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((options, rest)) => format!(
+            "{}{} [{} {}={}]{}",
+            indent,
+            source_type,
+            options.trim(),
+            name,
+            value,
+            rest
+        ),
+        None => format!("{}{} [{}={}] {}", indent, source_type, name, value, rest),
+    }
+}
+
+/// A globally trusted key to move to its own key file.
+#[derive(Debug, Clone)]
+pub struct MigratedKey {
+    /// Information about the key.
+    pub info: KeyInfo,
+
+    /// The globally trusted keyring the key is in.
+    pub keyring: PathBuf,
+
+    /// The key file to install the key to.
+    pub path: PathBuf,
+
+    /// The key, exported from the globally trusted keyring.
+    key: Key,
+
+    /// Whether the key is already installed at `path`.
+    installed: bool,
+}
+
+/// A source file to rewrite so its entries reference the migrated keys.
+#[derive(Debug, Clone)]
+struct SourceFileUpdate {
+    path: PathBuf,
+    contents: Vec<u8>,
+    entries: usize,
+}
+
+/// A globally trusted keyring to remove migrated keys from.
+#[derive(Debug, Clone)]
+struct TrustedKeyringUpdate {
+    path: PathBuf,
+
+    /// The keys which weren't migrated, or `None` if every key was.
+    remaining: Option<Key>,
+}
+
+/// A globally trusted key along with the keyring it's in.
+struct TrustedKey {
+    info: KeyInfo,
+    keyring: PathBuf,
+    keyring_key: Key,
+}
+
+/// A plan for migrating keys out of the globally trusted keyrings.
+#[derive(Debug, Clone)]
+pub struct MigrationPlan {
+    keys: Vec<MigratedKey>,
+    source_files: Vec<SourceFileUpdate>,
+    trusted_keyrings: Vec<TrustedKeyringUpdate>,
+
+    /// Source entries which use the globally trusted keys, but we couldn't tell which key signs
+    /// them, such as because apt hasn't downloaded their metadata yet.
+    unresolved: Vec<PathBuf>,
+
+    /// Globally trusted keys which don't sign any source we know of.
+    unused: Vec<KeyInfo>,
+
+    /// Whether the migrated keys were removed from the globally trusted keyrings.
+    removed: bool,
+}
+
+impl MigrationPlan {
+    /// Plan migrating the globally trusted keys in `locations`.
+    pub fn new(client: &dyn PgpClient, locations: &MigrateLocations) -> eyre::Result<Self> {
+        let mut trusted_keys = Vec::new();
+        let mut keyring_keys = Vec::new();
+
+        for keyring in installed_keyrings(client, &locations.keyrings)
+            .wrap_err("failed reading installed keyrings")?
+        {
+            if !keyring.trusted {
+                continue;
+            }
+
+            let keyring_key = client
+                .read_key(&keyring.path, KeyEncoding::Binary)
+                .wrap_err_with(|| format!("failed reading keyring: {}", keyring.path.display()))?;
+
+            for info in keyring.keys {
+                trusted_keys.push(TrustedKey {
+                    info,
+                    keyring: keyring.path.clone(),
+                    keyring_key: keyring_key.clone(),
+                });
+            }
+
+            keyring_keys.push(keyring_key);
+        }
+
+        let mut plan = Self {
+            keys: Vec::new(),
+            source_files: Vec::new(),
+            trusted_keyrings: Vec::new(),
+            unresolved: Vec::new(),
+            unused: Vec::new(),
+            removed: false,
+        };
+
+        if trusted_keys.is_empty() {
+            return Ok(plan);
+        }
+
+        let mut finder = SignerFinder::new(
+            client,
+            &locations.lists_dir,
+            client
+                .combine_keys(&keyring_keys, KeyEncoding::Binary, KeyExportMode::Full)
+                .wrap_err("failed combining globally trusted keyrings")?,
+        );
+
+        for (path, kind) in source_file_paths(&locations.sources_dir)? {
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("sources")
+                .to_string();
+
+            let mut signed_by = |plan: &mut Self, options: &OptionMap| -> eyre::Result<_> {
+                if options.contains(KnownOptionName::SignedBy) {
+                    return Ok(None);
+                }
+
+                // gpg reports the primary key of the signer, which should be one of the globally
+                // trusted keys. If it isn't, we can't tell which key to migrate.
+                let signer = match finder.signers(options)?.into_iter().find(|signer| {
+                    trusted_keys
+                        .iter()
+                        .any(|key| &key.info.fingerprint == signer)
+                }) {
+                    Some(signer) => signer,
+                    None => {
+                        plan.unresolved.push(path.clone());
+                        return Ok(None);
+                    }
+                };
+
+                plan.migrate_key(client, &trusted_keys, &signer, &name, &locations.dest_dir)
+                    .map(Some)
+            };
+
+            let (contents, entries) = match kind {
+                SourceFileKind::Deb822 => {
+                    let contents = read_source_file(&path)?;
+                    let original_entries = parse_deb822_file(contents.as_bytes(), false)
+                        .wrap_err_with(|| {
+                            format!("failed parsing source file: {}", path.display())
+                        })?;
+                    let mut file_entries = original_entries.clone();
+                    let mut count = 0;
+
+                    for entry in &mut file_entries {
+                        if let Deb822Entry::Entry(options) = entry {
+                            if let Some(key_path) = signed_by(&mut plan, options)? {
+                                options.insert(
+                                    KnownOptionName::SignedBy,
+                                    key_path.to_string_lossy().as_ref(),
+                                );
+                                count += 1;
+                            }
+                        }
+                    }
+
+                    // Only add the new Signed-By fields, leaving the rest of the file the way the
+                    // user wrote it.
+                    (
+                        update_deb822_contents(&contents, &original_entries, &file_entries)?,
+                        count,
+                    )
+                }
+                SourceFileKind::OneLine => {
+                    let mut contents = String::new();
+                    let mut count = 0;
+
+                    for line in read_source_file(&path)?.lines() {
+                        let entry = line.trim();
+
+                        let options = match entry.starts_with('#') || entry.is_empty() {
+                            true => None,
+                            // Leave lines alone if apt wouldn't understand them either.
+                            false => parse_line_entry(entry).ok(),
+                        };
+
+                        match options.map(|options| signed_by(&mut plan, &options)) {
+                            Some(Ok(Some(key_path))) => {
+                                contents.push_str(&add_line_option(
+                                    line,
+                                    "signed-by",
+                                    &key_path.to_string_lossy(),
+                                ));
+                                count += 1;
+                            }
+                            Some(Err(err)) => return Err(err),
+                            Some(Ok(None)) | None => contents.push_str(line),
+                        }
+
+                        contents.push('\n');
+                    }
+
+                    (contents.into_bytes(), count)
+                }
+            };
+
+            if entries > 0 {
+                plan.source_files.push(SourceFileUpdate {
+                    path,
+                    contents,
+                    entries,
+                });
+            }
+        }
+
+        plan.plan_trusted_keyrings(client, &trusted_keys)?;
+
+        Ok(plan)
+    }
+
+    /// Plan migrating the globally trusted key with this `fingerprint` and return the path of the
+    /// key file it will be installed to.
+    ///
+    /// The key file is named after the source file `name` which uses it.
+    fn migrate_key(
+        &mut self,
+        client: &dyn PgpClient,
+        trusted_keys: &[TrustedKey],
+        fingerprint: &Fingerprint,
+        name: &str,
+        dest_dir: &Path,
+    ) -> eyre::Result<PathBuf> {
+        if let Some(key) = self
+            .keys
+            .iter()
+            .find(|key| &key.info.fingerprint == fingerprint)
+        {
+            return Ok(key.path.clone());
+        }
+
+        let trusted_key = match trusted_keys
+            .iter()
+            .find(|key| &key.info.fingerprint == fingerprint)
+        {
+            Some(key) => key,
+            None => bail!("the signer is not a globally trusted key: {}", fingerprint),
+        };
+
+        let key = client
+            .select_keys(
+                &trusted_key.keyring_key,
+                std::slice::from_ref(fingerprint),
+                KeyEncoding::Binary,
+            )
+            .wrap_err("failed exporting key from globally trusted keyring")?;
+
+        let key_fingerprints = client
+            .fingerprints(&key)
+            .wrap_err("failed getting fingerprints of signing key")?;
+
+        // Fall back to including part of the fingerprint in the file name if there's already a
+        // different key installed under the name of the source.
+        let short_id = &fingerprint.as_ref()[fingerprint.as_ref().len().saturating_sub(16)..];
+        let candidates = [
+            dest_dir.join(format!("{}.gpg", name)),
+            dest_dir.join(format!("{}-{}.gpg", name, short_id.to_lowercase())),
+        ];
+
+        for path in candidates.iter() {
+            if self.keys.iter().any(|key| &key.path == path) {
+                continue;
+            }
+
+            let installed = path.exists();
+
+            if installed && !has_same_keys(client, path, &key_fingerprints)? {
+                continue;
+            }
+
+            self.keys.push(MigratedKey {
+                info: trusted_key.info.clone(),
+                keyring: trusted_key.keyring.clone(),
+                path: path.clone(),
+                key,
+                installed,
+            });
+
+            return Ok(path.clone());
+        }
+
+        bail!(Error::KeyFileAlreadyExists {
+            path: candidates[candidates.len() - 1].clone()
+        });
+    }
+
+    /// Plan removing the migrated keys from the globally trusted keyrings.
+    fn plan_trusted_keyrings(
+        &mut self,
+        client: &dyn PgpClient,
+        trusted_keys: &[TrustedKey],
+    ) -> eyre::Result<()> {
+        let is_migrated = |fingerprint: &Fingerprint| {
+            self.keys
+                .iter()
+                .any(|key| &key.info.fingerprint == fingerprint)
+        };
+
+        let mut keyring_paths: Vec<&Path> = Vec::new();
+
+        for trusted_key in trusted_keys {
+            if !keyring_paths.contains(&trusted_key.keyring.as_path()) {
+                keyring_paths.push(&trusted_key.keyring);
+            }
+        }
+
+        let mut updates = Vec::new();
+        let mut unused = Vec::new();
+
+        for keyring_path in keyring_paths {
+            let keyring_keys = trusted_keys
+                .iter()
+                .filter(|key| key.keyring == keyring_path)
+                .collect::<Vec<_>>();
+
+            let remaining = keyring_keys
+                .iter()
+                .filter(|key| !is_migrated(&key.info.fingerprint))
+                .map(|key| key.info.fingerprint.clone())
+                .collect::<Vec<_>>();
+
+            unused.extend(
+                keyring_keys
+                    .iter()
+                    .filter(|key| !is_migrated(&key.info.fingerprint))
+                    .map(|key| key.info.clone()),
+            );
+
+            if remaining.len() == keyring_keys.len() {
+                continue;
+            }
+
+            let remaining_key = match remaining.as_slice() {
+                [] => None,
+                fingerprints => Some(
+                    client
+                        .select_keys(
+                            &keyring_keys[0].keyring_key,
+                            fingerprints,
+                            KeyFormat::from_path(keyring_path)
+                                .unwrap_or(KeyFormat::Binary)
+                                .encoding(),
+                        )
+                        .wrap_err("failed exporting keys from globally trusted keyring")?,
+                ),
+            };
+
+            updates.push(TrustedKeyringUpdate {
+                path: keyring_path.to_owned(),
+                remaining: remaining_key,
+            });
+        }
+
+        self.trusted_keyrings = updates;
+        self.unused = unused;
+
+        Ok(())
+    }
+
+    /// Whether it's safe to remove the migrated keys from the globally trusted keyrings.
+    ///
+    /// If there are sources which might still rely on the globally trusted keys, removing them could
+    /// break those sources.
+    pub fn can_remove_trusted(&self) -> bool {
+        !self.trusted_keyrings.is_empty() && self.unresolved.is_empty()
+    }
+
+    /// Install the migrated keys and update the source files to reference them.
+    pub fn install(&self) -> eyre::Result<()> {
+        for key in &self.keys {
+            if key.installed {
+                continue;
+            }
+
+            if let Some(dir) = key.path.parent() {
+                ensure_dir_exists(dir).wrap_err("failed creating keyring directory")?;
+            }
+
+            write_atomically(&key.path, key.key.as_ref())
+                .wrap_err_with(|| format!("failed installing key file: {}", key.path.display()))?;
+        }
+
+        for source_file in &self.source_files {
+            write_atomically(&source_file.path, &source_file.contents).wrap_err_with(|| {
+                format!("failed writing source file: {}", source_file.path.display())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the migrated keys from the globally trusted keyrings.
+    pub fn remove_trusted(&mut self) -> eyre::Result<()> {
+        for keyring in &self.trusted_keyrings {
+            match &keyring.remaining {
+                Some(key) => write_atomically(&keyring.path, key.as_ref()),
+                None => match fs::remove_file(&keyring.path) {
+                    Ok(()) => Ok(()),
+                    Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                        bail!(Error::PermissionDenied)
+                    }
+                    Err(err) => Err(err.into()),
+                },
+            }
+            .wrap_err_with(|| format!("failed updating keyring: {}", keyring.path.display()))?;
+        }
+
+        self.removed = true;
+
+        Ok(())
+    }
+}
+
+/// Open the source file at `path` for reading.
+fn open_source_file(path: &Path) -> eyre::Result<File> {
+    match File::open(path) {
+        Ok(file) => Ok(file),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => bail!(Error::PermissionDenied),
+        Err(err) => Err(err).wrap_err("failed opening source file"),
+    }
+}
+
+/// Read the contents of the source file at `path`.
+fn read_source_file(path: &Path) -> eyre::Result<String> {
+    io::read_to_string(open_source_file(path)?).wrap_err("failed reading source file")
+}
+
+impl fmt::Display for MigrationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.keys.is_empty() && self.unresolved.is_empty() {
+            writeln!(f, "There are no globally trusted signing keys to migrate.")?;
+        }
+
+        for key in &self.keys {
+            writeln!(f, "Migrated signing key: {}", key.path.display())?;
+            writeln!(f, "  - {}", key.info.fingerprint)?;
+
+            for user_id in &key.info.user_ids {
+                writeln!(f, "    {}", user_id)?;
+            }
+
+            writeln!(f, "  - from {}", key.keyring.display())?;
+        }
+
+        for source_file in &self.source_files {
+            writeln!(
+                f,
+                "Added `Signed-By` to {} entries in source file: {}",
+                source_file.entries,
+                source_file.path.display()
+            )?;
+        }
+
+        if self.removed {
+            for keyring in &self.trusted_keyrings {
+                match keyring.remaining {
+                    Some(_) => writeln!(
+                        f,
+                        "Removed migrated keys from globally trusted keyring: {}",
+                        keyring.path.display()
+                    )?,
+                    None => writeln!(
+                        f,
+                        "Removed globally trusted keyring: {}",
+                        keyring.path.display()
+                    )?,
+                }
+            }
+        } else if self.can_remove_trusted() {
+            writeln!(
+                f,
+                "\nThe migrated keys are still globally trusted. Run this command again with `--yes` to remove them from:"
+            )?;
+
+            for keyring in &self.trusted_keyrings {
+                writeln!(f, "  - {}", keyring.path.display())?;
+            }
+        }
+
+        if !self.unresolved.is_empty() {
+            let mut paths = self.unresolved.clone();
+            paths.dedup();
+
+            writeln!(
+                f,
+                "\nCould not tell which key signs some sources, so the globally trusted keys were left in place. Run `apt update` and try again. These source files have entries without `Signed-By`:"
+            )?;
+
+            for path in paths {
+                writeln!(f, "  - {}", path.display())?;
+            }
+        }
+
+        if !self.unused.is_empty() {
+            writeln!(
+                f,
+                "\nThese globally trusted keys don't sign any source, so they were left in place:"
+            )?;
+
+            for key in &self.unused {
+                writeln!(f, "  - {}", key.fingerprint)?;
+
+                for user_id in &key.user_ids {
+                    writeln!(f, "    {}", user_id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use xpct::{be_false, be_ok, be_true, equal, expect};
+
+    use crate::pgp::testing::{generate_key, gpg};
+    use crate::pgp::GnupgClient;
+
+    use super::*;
+
+    #[rstest]
+    #[case(
+        "deb https://example.com/debian stable main",
+        "deb [signed-by=/key.gpg] https://example.com/debian stable main"
+    )]
+    #[case(
+        "deb [arch=amd64] https://example.com/debian stable main",
+        "deb [arch=amd64 signed-by=/key.gpg] https://example.com/debian stable main"
+    )]
+    fn adds_option_to_line_entry(#[case] line: &str, #[case] expected: &str) {
+        expect!(add_line_option(line, "signed-by", "/key.gpg")).to(equal(expected.to_string()));
+    }
+
+    #[test]
+    fn migrates_key_which_signs_sources() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+        let root = home.path();
+
+        let locations = MigrateLocations {
+            sources_dir: root.join("sources.list.d"),
+            keyrings: KeyringLocations {
+                dirs: vec![root.join("keyrings")],
+                trusted_dir: root.join("trusted.gpg.d"),
+                trusted_file: root.join("trusted.gpg"),
+            },
+            lists_dir: root.join("lists"),
+            dest_dir: root.join("keyrings"),
+        };
+
+        for dir in [
+            &locations.sources_dir,
+            &locations.keyrings.trusted_dir,
+            &locations.lists_dir,
+        ] {
+            fs::create_dir_all(dir)?;
+        }
+
+        let repo_fingerprint = generate_key(root, "repo <repo@example.com>")?;
+        let unused_fingerprint = generate_key(root, "unused <unused@example.com>")?;
+
+        fs::write(
+            locations.keyrings.trusted_dir.join("repo.gpg"),
+            gpg(root, &["--export", &repo_fingerprint])?,
+        )?;
+        fs::write(
+            &locations.keyrings.trusted_file,
+            gpg(root, &["--export", &unused_fingerprint])?,
+        )?;
+
+        let release_path = root.join("Release");
+        fs::write(&release_path, "Origin: Example\n")?;
+
+        gpg(
+            root,
+            &[
+                "--local-user",
+                &repo_fingerprint,
+                "--output",
+                &locations
+                    .lists_dir
+                    .join("example.com_debian_dists_stable_InRelease")
+                    .to_string_lossy(),
+                "--clearsign",
+                &release_path.to_string_lossy(),
+            ],
+        )?;
+
+        let deb822_path = locations.sources_dir.join("example.sources");
+        let line_path = locations.sources_dir.join("legacy.list");
+
+        fs::write(
+            &deb822_path,
+            "#comment\nURIs: https://example.com/debian\nTypes: deb\nSuites: stable\nComponents: main\n",
+        )?;
+        fs::write(
+            &line_path,
+            "# A comment\ndeb https://example.com/debian stable main\n",
+        )?;
+
+        let client = GnupgClient::new("gpg");
+        let mut plan = MigrationPlan::new(&client, &locations)?;
+
+        expect!(plan.can_remove_trusted()).to(be_true());
+
+        plan.install()?;
+        plan.remove_trusted()?;
+
+        let key_path = locations.dest_dir.join("example.gpg");
+
+        expect!(client.fingerprints(&client.read_key(&key_path, KeyEncoding::Binary)?))
+            .to(be_ok())
+            .map(|fingerprints| fingerprints[0].to_string())
+            .to(equal(repo_fingerprint));
+
+        expect!(fs::read_to_string(&deb822_path))
+            .to(be_ok())
+            .to(equal(format!(
+                "#comment\nURIs: https://example.com/debian\nTypes: deb\nSuites: stable\nComponents: main\nSigned-By: {}\n",
+                key_path.display()
+            )));
+
+        expect!(fs::read_to_string(&line_path))
+            .to(be_ok())
+            .to(equal(format!(
+                "# A comment\ndeb [signed-by={}] https://example.com/debian stable main\n",
+                key_path.display()
+            )));
+
+        expect!(locations.keyrings.trusted_dir.join("repo.gpg").exists()).to(be_false());
+        expect!(locations.keyrings.trusted_file.exists()).to(be_true());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,562 @@
+//! Downloading signing keys and keyring packages over HTTP.
+
+#[cfg(test)]
+pub mod testing;
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use eyre::{bail, eyre, WrapErr};
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::{Certificate, Identity, Proxy, Url};
+
+use crate::auth::AuthStore;
+use crate::config::ClientCertificate;
+use crate::error::Error;
+use crate::proxy::{ProxyConfig, ProxySetting};
+
+/// The most redirects to follow before giving up.
+const MAX_REDIRECTS: usize = 10;
+
+/// The only URL scheme that can't be tampered with in transit.
+const SECURE_SCHEME: &str = "https";
+
+/// The line which starts each certificate in a PEM file.
+const PEM_CERTIFICATE_HEADER: &str = "-----BEGIN CERTIFICATE-----";
+
+/// Options for how files are downloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadOptions {
+    /// How long to wait to connect to the server.
+    pub connect_timeout: Duration,
+
+    /// How long to wait for the whole download, including connecting.
+    pub timeout: Duration,
+
+    /// The largest file to download, in bytes.
+    pub max_size: u64,
+
+    /// The path of a PEM-encoded bundle of CA certificates to trust in addition to the system's.
+    pub ca_cert: Option<PathBuf>,
+
+    /// The client certificate to present to servers which ask for one.
+    pub client_cert: Option<ClientCertificate>,
+
+    /// Extra HTTP headers to authenticate with, in `Name: value` format.
+    pub auth_headers: Vec<String>,
+
+    /// The path of apt's `auth.conf.d` directory to read credentials from.
+    pub auth_dir: Option<PathBuf>,
+
+    /// The path of a netrc file to read credentials from.
+    pub netrc: Option<PathBuf>,
+
+    /// The proxies to download through.
+    pub proxy: ProxyConfig,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+            max_size: 10 * 1024 * 1024,
+            ca_cert: None,
+            client_cert: None,
+            auth_headers: Vec::new(),
+            auth_dir: None,
+            netrc: None,
+            proxy: ProxyConfig::default(),
+        }
+    }
+}
+
+/// Return `url` with any password replaced, so it's safe to show to the user.
+pub fn redact_url(url: &Url) -> Url {
+    let mut url = url.clone();
+
+    if url.password().is_some() {
+        // This can only fail for URLs which can't have a password in the first place.
+        let _ = url.set_password(Some("REDACTED"));
+    }
+
+    url
+}
+
+/// Check whether to follow a redirect from the `previous` URLs to `next`.
+///
+/// This refuses redirects from HTTPS to any other scheme, since they would let someone on the
+/// network path tamper with a download the user asked to be secure.
+fn check_redirect(previous: &[Url], next: &Url) -> Result<(), Error> {
+    if previous.len() > MAX_REDIRECTS {
+        return Err(Error::TooManyRedirects {
+            url: redact_url(&previous[0]).to_string(),
+        });
+    }
+
+    let was_secure = previous.iter().any(|url| url.scheme() == SECURE_SCHEME);
+
+    if was_secure && next.scheme() != SECURE_SCHEME {
+        return Err(Error::InsecureRedirect {
+            from: previous
+                .last()
+                .map(|url| redact_url(url).to_string())
+                .unwrap_or_default(),
+            to: redact_url(next).to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Parse `headers` in `Name: value` format.
+///
+/// The values are marked as sensitive, and never included in error messages.
+fn parse_auth_headers(headers: &[String]) -> Result<HeaderMap, Error> {
+    let mut header_map = HeaderMap::new();
+
+    for header in headers {
+        let (name, value) = header.split_once(':').ok_or(Error::MalformedAuthHeader)?;
+
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| Error::MalformedAuthHeader)?;
+        let mut value =
+            HeaderValue::from_str(value.trim()).map_err(|_| Error::MalformedAuthHeader)?;
+        value.set_sensitive(true);
+
+        header_map.append(name, value);
+    }
+
+    Ok(header_map)
+}
+
+/// Read the credentials in the files in `options`.
+///
+/// Credentials in apt's `auth.conf.d` take precedence over the ones in the netrc file.
+fn read_credentials(options: &DownloadOptions) -> eyre::Result<AuthStore> {
+    let mut credentials = AuthStore::default();
+
+    if let Some(dir) = &options.auth_dir {
+        credentials.read_dir(dir)?;
+    }
+
+    if let Some(path) = &options.netrc {
+        credentials.read_file(path)?;
+    }
+
+    Ok(credentials)
+}
+
+/// Read the CA certificates in the PEM bundle at `path`.
+fn read_ca_certs(path: &Path) -> eyre::Result<Vec<Certificate>> {
+    let invalid = |reason: String| Error::InvalidCertificate {
+        path: path.to_owned(),
+        reason,
+    };
+
+    let bundle = fs::read_to_string(path)
+        .wrap_err(format!("failed reading CA certificate: {}", path.display()))?;
+
+    // Parsing a PEM certificate only reads the first one in the file.
+    let certs = bundle
+        .match_indices(PEM_CERTIFICATE_HEADER)
+        .map(|(start, _)| {
+            let end = bundle[start + 1..]
+                .find(PEM_CERTIFICATE_HEADER)
+                .map_or(bundle.len(), |end| start + 1 + end);
+
+            Certificate::from_pem(&bundle.as_bytes()[start..end])
+                .map_err(|err| invalid(err.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if certs.is_empty() {
+        bail!(invalid(String::from("The file contains no certificates.")));
+    }
+
+    Ok(certs)
+}
+
+/// Read the client certificate and private key in `client_cert`.
+fn read_identity(client_cert: &ClientCertificate) -> eyre::Result<Identity> {
+    let cert = fs::read(&client_cert.cert).wrap_err(format!(
+        "failed reading client certificate: {}",
+        client_cert.cert.display()
+    ))?;
+    let key = fs::read(&client_cert.key).wrap_err(format!(
+        "failed reading client certificate private key: {}",
+        client_cert.key.display()
+    ))?;
+
+    Identity::from_pkcs8_pem(&cert, &key).map_err(|err| {
+        eyre!(Error::InvalidClientCertificate {
+            cert: client_cert.cert.clone(),
+            reason: err.to_string(),
+        })
+    })
+}
+
+/// Configure `builder` to use the TLS certificates in `options`.
+fn configure_tls(
+    mut builder: ClientBuilder,
+    options: &DownloadOptions,
+) -> eyre::Result<ClientBuilder> {
+    if let Some(path) = &options.ca_cert {
+        for cert in read_ca_certs(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Some(client_cert) = &options.client_cert {
+        builder = builder.identity(read_identity(client_cert)?);
+    }
+
+    Ok(builder)
+}
+
+/// Configure `builder` to use the proxies in `config`.
+///
+/// If there are no proxy settings, reqwest uses the proxy environment variables.
+fn configure_proxy(builder: ClientBuilder, config: &ProxyConfig) -> ClientBuilder {
+    if config.is_empty() {
+        return builder;
+    }
+
+    let config = config.clone();
+
+    builder.proxy(Proxy::custom(move |url| match config.get(url) {
+        Some(ProxySetting::Url(proxy)) => Some(proxy.clone()),
+        Some(ProxySetting::Direct) | None => None,
+    }))
+}
+
+/// Convert an error from reqwest into a user-facing error where we can.
+fn map_err(url: &Url, err: reqwest::Error) -> eyre::Report {
+    if err.is_timeout() {
+        return eyre!(Error::KeyDownloadFailed {
+            url: redact_url(url).to_string(),
+            reason: String::from("The server took too long to respond."),
+        });
+    }
+
+    eyre!(err.with_url(redact_url(url)))
+}
+
+/// A client for downloading files over HTTP.
+pub trait HttpClient {
+    /// Download the file at `url` and return its contents.
+    fn download(&self, url: &Url) -> eyre::Result<Vec<u8>>;
+}
+
+/// An [`HttpClient`] which downloads files with reqwest.
+#[derive(Debug, Clone)]
+pub struct ReqwestClient {
+    client: Client,
+    max_size: u64,
+    headers: HeaderMap,
+    credentials: AuthStore,
+}
+
+impl ReqwestClient {
+    /// Create a new client which downloads files according to `options`.
+    pub fn new(options: &DownloadOptions) -> eyre::Result<Self> {
+        // We follow redirects ourselves so we can decide which URLs get the credentials.
+        let builder = Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .timeout(options.timeout)
+            .redirect(Policy::none());
+
+        let client = configure_tls(configure_proxy(builder, &options.proxy), options)?
+            .build()
+            .wrap_err("failed creating HTTP client")?;
+
+        Ok(Self {
+            client,
+            max_size: options.max_size,
+            headers: parse_auth_headers(&options.auth_headers)?,
+            credentials: read_credentials(options)?,
+        })
+    }
+
+    /// Add credentials to the `request` for `url`.
+    ///
+    /// The auth headers are only sent to the same origin as the `original` URL the user asked
+    /// for, so they aren't leaked to other servers it redirects to.
+    fn authenticate(
+        &self,
+        mut request: RequestBuilder,
+        url: &Url,
+        original: &Url,
+    ) -> RequestBuilder {
+        let mut has_authorization = !url.username().is_empty();
+
+        if url.origin() == original.origin() {
+            request = request.headers(self.headers.clone());
+            has_authorization |= self.headers.contains_key(AUTHORIZATION);
+        }
+
+        if !has_authorization {
+            if let Some(credentials) = self.credentials.find(url) {
+                request = request.basic_auth(&credentials.login, Some(&credentials.password));
+            }
+        }
+
+        request
+    }
+
+    /// Send a request for `url`, following any redirects.
+    fn send(&self, url: &Url) -> eyre::Result<(Url, Response)> {
+        let mut previous = Vec::new();
+        let mut current = url.clone();
+
+        loop {
+            let response = self
+                .authenticate(self.client.get(current.clone()), &current, url)
+                .send()
+                .map_err(|err| map_err(&current, err))?;
+
+            let location = match response.headers().get(LOCATION) {
+                Some(location) if response.status().is_redirection() => location,
+                _ => return Ok((current, response)),
+            };
+
+            let next = location
+                .to_str()
+                .ok()
+                .and_then(|location| current.join(location).ok())
+                .ok_or_else(|| Error::KeyDownloadFailed {
+                    url: redact_url(&current).to_string(),
+                    reason: String::from("The server redirected to an invalid URL."),
+                })?;
+
+            previous.push(current);
+            check_redirect(&previous, &next)?;
+            current = next;
+        }
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn download(&self, url: &Url) -> eyre::Result<Vec<u8>> {
+        let (url, response) = self.send(url)?;
+        let url = redact_url(&url);
+        let status = response.status();
+
+        if !status.is_success() {
+            bail!(Error::KeyDownloadFailed {
+                url: url.to_string(),
+                reason: match status.canonical_reason() {
+                    Some(reason_phrase) => format!("Error: {}", reason_phrase),
+                    None => format!("Error Code: {}", status.as_str()),
+                }
+            })
+        }
+
+        let too_large = || Error::DownloadTooLarge {
+            url: url.to_string(),
+            max_size: self.max_size,
+        };
+
+        // The server can lie about the size, so this is only a shortcut.
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_size)
+        {
+            bail!(too_large());
+        }
+
+        let mut contents = Vec::new();
+
+        response
+            .take(self.max_size + 1)
+            .read_to_end(&mut contents)
+            .wrap_err("failed reading response body")?;
+
+        if contents.len() as u64 > self.max_size {
+            bail!(too_large());
+        }
+
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use xpct::{be_err, be_ok, equal, expect};
+
+    use super::*;
+
+    /// Serve one HTTP request on localhost, and return the URL to request.
+    ///
+    /// The `respond` function is passed the request head and returns the whole response.
+    fn serve_with(respond: impl FnOnce(String) -> Vec<u8> + Send + 'static) -> eyre::Result<Url> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/key.gpg", listener.local_addr()?))?;
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf).unwrap() {
+                    0 => break,
+                    len => request.extend_from_slice(&buf[..len]),
+                }
+            }
+
+            let response = respond(String::from_utf8_lossy(&request).to_lowercase());
+            let _ = stream.write_all(&response);
+        });
+
+        Ok(url)
+    }
+
+    /// Serve one HTTP request on localhost with `body`, and return the URL to request.
+    fn serve_once(body: Vec<u8>) -> eyre::Result<Url> {
+        serve_with(move |_| {
+            let mut response = b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n".to_vec();
+            response.extend(body);
+            response
+        })
+    }
+
+    /// Serve one HTTP request on localhost, responding with the request head as the body.
+    fn serve_echo() -> eyre::Result<Url> {
+        serve_with(|request| {
+            format!("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{request}").into_bytes()
+        })
+    }
+
+    #[test]
+    fn rejects_ca_bundle_without_certificates() -> eyre::Result<()> {
+        let mut ca_cert = tempfile::NamedTempFile::new()?;
+        writeln!(ca_cert, "not a certificate")?;
+
+        let options = DownloadOptions {
+            ca_cert: Some(ca_cert.path().to_owned()),
+            ..Default::default()
+        };
+
+        expect!(ReqwestClient::new(&options))
+            .to(be_err())
+            .map(|err| err.downcast::<Error>().ok())
+            .to(equal(Some(Error::InvalidCertificate {
+                path: ca_cert.path().to_owned(),
+                reason: String::from("The file contains no certificates."),
+            })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn downloads_file_within_size_limit() -> eyre::Result<()> {
+        let url = serve_once(b"key".to_vec())?;
+        let client = ReqwestClient::new(&DownloadOptions::default())?;
+
+        expect!(client.download(&url))
+            .to(be_ok())
+            .to(equal(b"key".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_download_over_size_limit() -> eyre::Result<()> {
+        let url = serve_once(vec![0; 64])?;
+        let client = ReqwestClient::new(&DownloadOptions {
+            max_size: 32,
+            ..Default::default()
+        })?;
+
+        expect!(client.download(&url))
+            .to(be_err())
+            .map(|err| err.downcast::<Error>().ok())
+            .to(equal(Some(Error::DownloadTooLarge {
+                url: url.to_string(),
+                max_size: 32,
+            })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sends_credentials_from_auth_dir() -> eyre::Result<()> {
+        let url = serve_echo()?;
+        let auth_dir = tempfile::tempdir()?;
+
+        fs::write(
+            auth_dir.path().join("repo.conf"),
+            format!(
+                "machine http://{}/key.gpg login user password pass\n",
+                url.authority()
+            ),
+        )?;
+
+        let client = ReqwestClient::new(&DownloadOptions {
+            auth_dir: Some(auth_dir.path().to_owned()),
+            ..Default::default()
+        })?;
+
+        let request = String::from_utf8(client.download(&url)?)?;
+
+        // This is `user:pass` in base64.
+        expect!(request.contains("authorization: basic dxnlcjpwyxnz")).to(equal(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn does_not_send_auth_headers_to_other_origins() -> eyre::Result<()> {
+        let target = serve_echo()?;
+        let location = target.to_string();
+        let url = serve_with(move |_| {
+            format!("HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .into_bytes()
+        })?;
+
+        let client = ReqwestClient::new(&DownloadOptions {
+            auth_headers: vec![String::from("X-Api-Key: s3cret")],
+            ..Default::default()
+        })?;
+
+        let request = String::from_utf8(client.download(&url)?)?;
+
+        expect!(request.contains("s3cret")).to(equal(false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn errors_do_not_include_url_passwords() -> eyre::Result<()> {
+        let mut url = serve_with(|_| {
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+        })?;
+        url.set_username("user").ok();
+        url.set_password(Some("s3cret")).ok();
+
+        let client = ReqwestClient::new(&DownloadOptions::default())?;
+
+        expect!(client.download(&url))
+            .to(be_err())
+            .map(|err| format!("{:?}", err).contains("s3cret"))
+            .to(equal(false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_auth_header() {
+        expect!(parse_auth_headers(&[String::from("Bearer s3cret")]))
+            .to(be_err())
+            .to(equal(Error::MalformedAuthHeader));
+    }
+}
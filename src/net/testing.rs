@@ -0,0 +1,51 @@
+//! An in-memory HTTP client for tests which need to download files.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use eyre::bail;
+use reqwest::Url;
+
+use crate::error::Error;
+
+use super::{redact_url, HttpClient};
+
+/// An [`HttpClient`] which serves files from memory instead of the network.
+///
+/// It records every URL it's asked for, so tests can check what was downloaded.
+#[derive(Debug, Default)]
+pub struct MemoryClient {
+    files: HashMap<Url, Vec<u8>>,
+    requests: RefCell<Vec<Url>>,
+}
+
+impl MemoryClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `contents` at `url`.
+    pub fn with_file(mut self, url: &Url, contents: impl Into<Vec<u8>>) -> Self {
+        self.files.insert(url.clone(), contents.into());
+        self
+    }
+
+    /// The URLs that have been downloaded, in order.
+    pub fn requests(&self) -> Vec<Url> {
+        self.requests.borrow().clone()
+    }
+}
+
+impl HttpClient for MemoryClient {
+    fn download(&self, url: &Url) -> eyre::Result<Vec<u8>> {
+        self.requests.borrow_mut().push(url.clone());
+
+        match self.files.get(url) {
+            Some(contents) => Ok(contents.clone()),
+            None => bail!(Error::KeyDownloadFailed {
+                url: redact_url(url).to_string(),
+                reason: String::from("Error: Not Found"),
+            }),
+        }
+    }
+}
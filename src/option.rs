@@ -1,5 +1,6 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::{self, Write};
 use std::str::FromStr;
 
 use eyre::bail;
@@ -25,6 +26,8 @@ pub enum KnownOptionName {
     Suites,
     Components,
     SignedBy,
+    KeySource,
+    KeyMode,
     Trusted,
     Architectures,
     Languages,
@@ -64,6 +67,8 @@ impl KnownOptionName {
             ValidUntilMin => "Valid-Until-Min",
             ValidUntilMax => "Valid-Until-Max",
             RepolibName => "X-Repolib-Name",
+            KeySource => "X-Debctl-Key-Source",
+            KeyMode => "X-Debctl-Key-Mode",
         }
     }
 }
@@ -284,6 +289,19 @@ impl OptionValue {
             }
         }
     }
+
+    /// Write this value as a deb822 field named `name`.
+    pub fn write_deb822_field(&self, name: &str, mut dest: impl Write) -> io::Result<()> {
+        let value = self.to_deb822();
+
+        // Multiline values start on the next line and already end with a newline. Adding another
+        // would leave a blank line, which would end the stanza.
+        if value.starts_with('\n') {
+            write!(&mut dest, "{}:{}", name, value)
+        } else {
+            writeln!(&mut dest, "{}: {}", name, value)
+        }
+    }
 }
 
 pub type OptionPair = (OptionName, OptionValue);
@@ -317,6 +335,13 @@ impl OptionMap {
             let value: OptionValue = match key {
                 SigningKey::File { path } => path.to_string_lossy().to_string().into(),
                 SigningKey::Inline { value } => value,
+                SigningKey::Fingerprints { path, fingerprints } => {
+                    // apt accepts a keyring path followed by the fingerprints of the keys in it
+                    // that are allowed to sign the repository.
+                    let mut values = vec![path.to_string_lossy().to_string()];
+                    values.extend(fingerprints.iter().map(ToString::to_string));
+                    values.into()
+                }
             };
 
             self.insert(KnownOptionName::SignedBy, value);
@@ -325,11 +350,30 @@ impl OptionMap {
         Ok(())
     }
 
+    /// Remove the given option from the map, returning its value if it was there.
+    pub fn remove(&mut self, name: impl Into<OptionName>) -> Option<OptionValue> {
+        self.0.remove(&name.into())
+    }
+
+    /// Return the value of the given option, if this map contains it.
+    pub fn get(&self, name: impl Into<OptionName>) -> Option<&OptionValue> {
+        self.0.get(&name.into())
+    }
+
     /// Return whether this option map contains the given option.
     pub fn contains(&self, name: impl Into<OptionName>) -> bool {
         self.0.contains_key(&name.into())
     }
 
+    /// Write the options in this map as a deb822 stanza.
+    pub fn write_deb822(&self, mut dest: impl Write) -> io::Result<()> {
+        for (name, value) in self.options() {
+            value.write_deb822_field(name.to_deb822(), &mut dest)?;
+        }
+
+        Ok(())
+    }
+
     /// Return the options in this map in their canonical order.
     ///
     /// Known options are ordered consistently. Custom options are sorted by their key and come
@@ -341,8 +385,7 @@ impl OptionMap {
             .filter(|(name, _)| !name.is_known())
             .collect::<Vec<_>>();
 
-        // We cannot use `Vec::sort_by_key` here because of the lifetimes.
-        custom_options.sort_by(|(first, _), (second, _)| first.cmp(second));
+        custom_options.sort_by_key(|(name, _)| *name);
 
         let mut all_options = Vec::with_capacity(self.0.len());
 
@@ -462,4 +505,25 @@ mod tests {
             .to(be_ok())
             .to(equal(Error::ConflictingKeyLocations));
     }
+
+    #[test]
+    fn multiline_values_do_not_end_the_stanza() -> io::Result<()> {
+        let mut map = OptionMap::new();
+
+        map.insert(KnownOptionName::Types, vec![SourceType::Deb]);
+        map.insert(
+            KnownOptionName::SignedBy,
+            OptionValue::Multiline(vec!["first".into(), String::new(), "last".into()]),
+        );
+        map.insert(OptionName::Custom("option-a".into()), "value-a");
+
+        let mut output = Vec::new();
+        map.write_deb822(&mut output)?;
+
+        expect!(String::from_utf8_lossy(&output).into_owned()).to(equal(String::from(
+            "Types: deb\nSigned-By:\n first\n .\n last\noption-a: value-a\n",
+        )));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,406 @@
+//! Extracting signing keys from vendor keyring packages.
+
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, WrapErr};
+use flate2::read::GzDecoder;
+use reqwest::Url;
+use ruzstd::StreamingDecoder;
+
+use crate::error::Error;
+use crate::key::KEY_FILE_EXTENSIONS;
+use crate::net::redact_url;
+
+/// The name of the member of a `.deb` archive which contains the package's files, minus the
+/// compression extension.
+const DATA_MEMBER_PREFIX: &str = "data.tar";
+
+/// The directories in a package where keyring packages install their keyrings.
+const KEYRING_DIRS: [&str; 2] = ["usr/share/keyrings", "etc/apt/trusted.gpg.d"];
+
+/// The most data to decompress from a package, in bytes.
+///
+/// Keyring packages are tiny, and this keeps a package which decompresses to something enormous
+/// from using up all the memory.
+const MAX_DATA_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The largest keyring to read from a package, in bytes.
+const MAX_KEYRING_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The file extension of a `.deb` package.
+pub const PACKAGE_EXTENSION: &str = "deb";
+
+/// The location of a `.deb` package.
+#[derive(Debug, Clone)]
+pub enum PackageLocation {
+    /// Download the package from a URL.
+    Download { url: Url },
+
+    /// Read the package from a path.
+    File { path: PathBuf },
+}
+
+impl fmt::Display for PackageLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Download { url } => write!(f, "{}", redact_url(url)),
+            Self::File { path } => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A keyring file in a package.
+#[derive(Debug, Clone)]
+pub struct PackageKeyring {
+    /// The path the package installs the keyring to, relative to the root.
+    pub path: PathBuf,
+
+    /// The contents of the keyring.
+    pub contents: Vec<u8>,
+}
+
+impl PackageKeyring {
+    /// Return whether `name` refers to this keyring, either by its file name or its path.
+    fn is_named(&self, name: &str) -> bool {
+        let name = Path::new(name.trim_start_matches('/'));
+
+        self.path == name || self.path.file_name() == Some(name.as_os_str())
+    }
+}
+
+/// Return whether `path` looks like the path of a `.deb` package.
+pub fn is_package_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|extension| extension == PACKAGE_EXTENSION)
+}
+
+/// The error for when decompressing a package produces more than [`MAX_DATA_SIZE`] bytes.
+fn data_too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!(
+            "package data is larger than the limit of {} bytes",
+            MAX_DATA_SIZE
+        ),
+    )
+}
+
+/// A reader which fails once more than [`MAX_DATA_SIZE`] bytes have been read from it.
+struct LimitedReader<R>(io::Take<R>);
+
+impl<R: Read> LimitedReader<R> {
+    fn new(inner: R) -> Self {
+        Self(inner.take(MAX_DATA_SIZE + 1))
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.0.read(buf)?;
+
+        if self.0.limit() == 0 {
+            return Err(data_too_large());
+        }
+
+        Ok(len)
+    }
+}
+
+/// A writer which fails once more than [`MAX_DATA_SIZE`] bytes have been written to it.
+#[derive(Debug, Default)]
+struct LimitedWriter(Vec<u8>);
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if (self.0.len() + buf.len()) as u64 > MAX_DATA_SIZE {
+            return Err(data_too_large());
+        }
+
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decompress the tarball in the `data.tar.*` member of a `.deb` archive, based on its `name`.
+///
+/// This fails if the tarball is larger than [`MAX_DATA_SIZE`].
+fn decompress_data<'a>(name: &str, member: impl Read + 'a) -> eyre::Result<Box<dyn Read + 'a>> {
+    Ok(match name.strip_prefix(DATA_MEMBER_PREFIX) {
+        Some("") => Box::new(LimitedReader::new(member)),
+        Some(".gz") => Box::new(LimitedReader::new(GzDecoder::new(member))),
+        Some(".xz") => {
+            // xz can only be decompressed all at once, so stop before it fills up the memory.
+            let mut tarball = LimitedWriter::default();
+
+            lzma_rs::xz_decompress(&mut BufReader::new(member), &mut tarball)
+                .wrap_err("failed decompressing package data")?;
+
+            Box::new(io::Cursor::new(tarball.0))
+        }
+        Some(".zst") => Box::new(LimitedReader::new(
+            StreamingDecoder::new(member).wrap_err("failed decompressing package data")?,
+        )),
+        _ => bail!(Error::UnsupportedPackageCompression {
+            member: name.to_string(),
+        }),
+    })
+}
+
+/// Find the keyring files in the `.deb` `package`.
+///
+/// The package is parsed in-process, since the tools to unpack it might not be installed.
+pub fn package_keyrings(package: impl Read) -> eyre::Result<Vec<PackageKeyring>> {
+    let mut archive = ar::Archive::new(package);
+
+    while let Some(entry) = archive.next_entry() {
+        let entry = entry.wrap_err("failed reading package archive")?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).into_owned();
+
+        if !name.starts_with(DATA_MEMBER_PREFIX) {
+            continue;
+        }
+
+        let mut tarball = tar::Archive::new(decompress_data(&name, entry)?);
+        let mut keyrings = Vec::new();
+
+        for file in tarball.entries().wrap_err("failed reading package data")? {
+            let file = file.wrap_err("failed reading package data")?;
+
+            if !file.header().entry_type().is_file() {
+                continue;
+            }
+
+            // Paths in package tarballs usually start with `./`.
+            let path = file.path().wrap_err("failed reading package data")?;
+            let path = path.strip_prefix(".").unwrap_or(&path).to_owned();
+
+            let is_keyring = KEYRING_DIRS
+                .iter()
+                .any(|dir| path.parent() == Some(Path::new(dir)))
+                && path.extension().is_some_and(|extension| {
+                    KEY_FILE_EXTENSIONS
+                        .iter()
+                        .any(|key_extension| extension == *key_extension)
+                });
+
+            if is_keyring {
+                let mut contents = Vec::new();
+
+                file.take(MAX_KEYRING_SIZE + 1)
+                    .read_to_end(&mut contents)
+                    .wrap_err("failed reading keyring from package")?;
+
+                if contents.len() as u64 > MAX_KEYRING_SIZE {
+                    bail!(Error::MalformedPackage {
+                        reason: format!(
+                            "the keyring `{}` is larger than the limit of {} bytes",
+                            path.display(),
+                            MAX_KEYRING_SIZE
+                        ),
+                    });
+                }
+
+                keyrings.push(PackageKeyring { path, contents });
+            }
+        }
+
+        return Ok(keyrings);
+    }
+
+    bail!(Error::MalformedPackage {
+        reason: format!("it has no `{}` member", DATA_MEMBER_PREFIX),
+    })
+}
+
+/// Choose the keyring named `name` from the `keyrings` in `package`.
+///
+/// If `name` is `None`, the package must contain exactly one keyring.
+pub fn select_keyring<'a>(
+    package: &PackageLocation,
+    keyrings: &'a [PackageKeyring],
+    name: Option<&str>,
+) -> eyre::Result<&'a PackageKeyring> {
+    let paths = || {
+        keyrings
+            .iter()
+            .map(|keyring| keyring.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+
+    match (name, keyrings) {
+        (Some(name), _) => match keyrings.iter().find(|keyring| keyring.is_named(name)) {
+            Some(keyring) => Ok(keyring),
+            None => bail!(Error::KeyringNotInPackage {
+                package: package.to_string(),
+                keyring: name.to_string(),
+                keyrings: paths(),
+            }),
+        },
+        (None, []) => bail!(Error::NoKeyringInPackage {
+            package: package.to_string(),
+        }),
+        (None, [keyring]) => Ok(keyring),
+        (None, _) => bail!(Error::MultipleKeyringsInPackage {
+            package: package.to_string(),
+            keyrings: paths(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rstest::rstest;
+    use xpct::{be_err, be_ok, equal, expect};
+
+    use super::*;
+
+    /// Build a `.deb` package containing `files`, with a gzipped data tarball.
+    fn build_package(files: &[(&str, &[u8])]) -> eyre::Result<Vec<u8>> {
+        let mut tarball = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            tarball.append_data(&mut header, path, *contents)?;
+        }
+
+        let data = tarball.into_inner()?.finish()?;
+
+        let mut package = ar::Builder::new(Vec::new());
+
+        for (name, contents) in [
+            ("debian-binary", b"2.0\n".as_slice()),
+            ("control.tar.gz", &[]),
+            ("data.tar.gz", &data),
+        ] {
+            package.append(
+                &ar::Header::new(name.as_bytes().to_vec(), contents.len() as u64),
+                contents,
+            )?;
+        }
+
+        let mut package = package.into_inner()?;
+        package.flush()?;
+
+        Ok(package)
+    }
+
+    #[test]
+    fn rejects_keyrings_over_the_size_limit() -> eyre::Result<()> {
+        let keyring = vec![0; MAX_KEYRING_SIZE as usize + 1];
+        let package = build_package(&[("./usr/share/keyrings/vendor.gpg", &keyring)])?;
+
+        expect!(package_keyrings(package.as_slice()))
+            .to(be_err())
+            .map(|err| err.downcast::<Error>())
+            .to(be_ok())
+            .to(equal(Error::MalformedPackage {
+                reason: format!(
+                    "the keyring `usr/share/keyrings/vendor.gpg` is larger than the limit of {} bytes",
+                    MAX_KEYRING_SIZE
+                ),
+            }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_decompressing_data_over_the_size_limit() {
+        expect!(io::copy(
+            &mut LimitedReader::new(io::repeat(0).take(MAX_DATA_SIZE)),
+            &mut io::sink()
+        ))
+        .to(be_ok())
+        .to(equal(MAX_DATA_SIZE));
+
+        expect!(io::copy(
+            &mut LimitedReader::new(io::repeat(0)),
+            &mut io::sink()
+        ))
+        .to(be_err());
+    }
+
+    #[test]
+    fn finds_keyrings_in_package() -> eyre::Result<()> {
+        let package = build_package(&[
+            ("./usr/share/keyrings/vendor-archive-keyring.gpg", b"repo"),
+            ("./etc/apt/trusted.gpg.d/vendor.asc", b"trusted"),
+            ("./usr/share/doc/vendor-archive-keyring/copyright", b"doc"),
+            ("./usr/share/keyrings/nested/other.gpg", b"nested"),
+        ])?;
+
+        let keyrings = package_keyrings(package.as_slice())?;
+
+        expect!(keyrings
+            .iter()
+            .map(|keyring| (keyring.path.clone(), keyring.contents.clone()))
+            .collect::<Vec<_>>())
+        .to(equal(vec![
+            (
+                PathBuf::from("usr/share/keyrings/vendor-archive-keyring.gpg"),
+                b"repo".to_vec(),
+            ),
+            (
+                PathBuf::from("etc/apt/trusted.gpg.d/vendor.asc"),
+                b"trusted".to_vec(),
+            ),
+        ]));
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(
+        Some("vendor-archive-keyring.gpg"),
+        Some("usr/share/keyrings/vendor-archive-keyring.gpg")
+    )]
+    #[case(
+        Some("/etc/apt/trusted.gpg.d/vendor.asc"),
+        Some("etc/apt/trusted.gpg.d/vendor.asc")
+    )]
+    #[case(Some("missing.gpg"), None)]
+    #[case(None, None)]
+    fn selects_keyring_by_name(
+        #[case] name: Option<&str>,
+        #[case] expected: Option<&str>,
+    ) -> eyre::Result<()> {
+        let package = build_package(&[
+            ("./usr/share/keyrings/vendor-archive-keyring.gpg", b"repo"),
+            ("./etc/apt/trusted.gpg.d/vendor.asc", b"trusted"),
+        ])?;
+
+        let keyrings = package_keyrings(package.as_slice())?;
+        let location = PackageLocation::File {
+            path: PathBuf::from("vendor-archive-keyring_1.0_all.deb"),
+        };
+
+        let selected = select_keyring(&location, &keyrings, name);
+
+        match expected {
+            Some(path) => {
+                expect!(selected)
+                    .to(be_ok())
+                    .map(|keyring| keyring.path.clone())
+                    .to(equal(PathBuf::from(path)));
+            }
+            None => {
+                expect!(selected).to(be_err());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use std::str::FromStr;
 use eyre::{bail, WrapErr};
 use pest::Parser;
 use pest_derive::Parser;
+use strum::IntoEnumIterator;
 
 use crate::error::Error;
 use crate::option::{KnownOptionName, OptionMap, OptionName, OptionPair, OptionValue};
@@ -194,6 +195,149 @@ pub fn parse_line_file(
     Ok(entry_list)
 }
 
+/// An entry in a deb822-style source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deb822Entry {
+    Entry(OptionMap),
+    Comment(String),
+}
+
+/// Parse the name of an option in a deb822-style source file.
+///
+/// Unlike in one-line-style entries, unknown option names are allowed, since apt ignores them.
+pub fn parse_deb822_option_name(name: &str) -> OptionName {
+    // This also matches the extension fields like `X-Repolib-Name` which can't be passed as a
+    // custom option.
+    if let Some(known_name) =
+        KnownOptionName::iter().find(|known_name| known_name.to_deb822().eq_ignore_ascii_case(name))
+    {
+        return known_name.into();
+    }
+
+    match KnownOptionName::from_str(name) {
+        Ok(known_name) => known_name.into(),
+        Err(_) => OptionName::Custom(name.to_string()),
+    }
+}
+
+/// Parse the value of an option in a deb822-style source file.
+///
+/// `first_line` is the part of the field after the colon and `continuation_lines` are any
+/// following lines that start with whitespace.
+fn parse_deb822_option_value(first_line: &str, continuation_lines: &[String]) -> OptionValue {
+    if first_line.trim().is_empty() && !continuation_lines.is_empty() {
+        return OptionValue::Multiline(
+            continuation_lines
+                .iter()
+                .map(|line| match line.trim() {
+                    // Blank lines are escaped with a dot.
+                    "." => String::new(),
+                    line => line.to_string(),
+                })
+                .collect(),
+        );
+    }
+
+    // This is a folded field, where whitespace and line breaks are equivalent.
+    std::iter::once(first_line)
+        .chain(continuation_lines.iter().map(String::as_str))
+        .flat_map(str::split_whitespace)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// A field in a deb822-style source file which hasn't been parsed into an option yet.
+struct Deb822Field {
+    name: String,
+    first_line: String,
+    continuation_lines: Vec<String>,
+}
+
+impl Deb822Field {
+    /// Parse this field and insert it into `option_map`.
+    fn insert_into(self, option_map: &mut OptionMap) {
+        option_map.insert(
+            parse_deb822_option_name(&self.name),
+            parse_deb822_option_value(&self.first_line, &self.continuation_lines),
+        );
+    }
+}
+
+/// Parse a file of deb822-style source entries.
+///
+/// Comments are preserved unless `skip_comments` is true. Comments inside a stanza come before
+/// that stanza in the output.
+pub fn parse_deb822_file(
+    mut file: impl Read,
+    skip_comments: bool,
+) -> eyre::Result<Vec<Deb822Entry>> {
+    let mut entry_list = Vec::new();
+    let mut option_map = OptionMap::new();
+    let mut has_options = false;
+
+    let mut current_field: Option<Deb822Field> = None;
+
+    for line_result in BufReader::new(&mut file).lines() {
+        let line = line_result.wrap_err("failed reading source file")?;
+
+        if line.trim().is_empty() {
+            // A blank line ends the current stanza.
+            if let Some(field) = current_field.take() {
+                field.insert_into(&mut option_map);
+            }
+
+            if has_options {
+                entry_list.push(Deb822Entry::Entry(std::mem::replace(
+                    &mut option_map,
+                    OptionMap::new(),
+                )));
+                has_options = false;
+            }
+        } else if line.starts_with(COMMENT_CHAR) {
+            if !skip_comments {
+                let comment = line.trim_start_matches(COMMENT_CHAR).trim();
+                entry_list.push(Deb822Entry::Comment(comment.to_string()));
+            }
+        } else if line.starts_with(char::is_whitespace) {
+            match &mut current_field {
+                Some(field) => field.continuation_lines.push(line),
+                None => bail!(Error::MalformedDeb822Entry {
+                    reason: format!("This continuation line doesn't follow a field: `{line}`."),
+                }),
+            }
+        } else {
+            if let Some(field) = current_field.take() {
+                field.insert_into(&mut option_map);
+            }
+
+            match line.split_once(':') {
+                Some((name, value)) if !name.trim().is_empty() => {
+                    current_field = Some(Deb822Field {
+                        name: name.trim().to_string(),
+                        first_line: value.to_string(),
+                        continuation_lines: Vec::new(),
+                    });
+                    has_options = true;
+                }
+                _ => bail!(Error::MalformedDeb822Entry {
+                    reason: format!("This line is not a `Name: value` field: `{line}`."),
+                }),
+            }
+        }
+    }
+
+    if let Some(field) = current_field {
+        field.insert_into(&mut option_map);
+    }
+
+    if has_options {
+        entry_list.push(Deb822Entry::Entry(option_map));
+    }
+
+    Ok(entry_list)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::error::Error;
@@ -482,4 +626,90 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn parses_valid_deb822_file() -> eyre::Result<()> {
+        let file = "\
+# comment
+Types: deb deb-src
+URIs: https://example.com
+Suites: suite
+Components: main contrib
+X-Custom: value
+
+Types: deb
+URIs: https://example.com
+Suites: other
+Enabled: no
+";
+
+        let entries = parse_deb822_file(file.as_bytes(), false)?;
+
+        expect!(entries.clone()).to(have_len(3)).to(match_elements([
+            equal(Deb822Entry::Comment("comment".into())),
+            match_pattern(pattern!(Deb822Entry::Entry(_))),
+            match_pattern(pattern!(Deb822Entry::Entry(_))),
+        ]));
+
+        let options = match &entries[1] {
+            Deb822Entry::Entry(options) => options,
+            _ => unreachable!(),
+        };
+
+        expect!(options.get(KnownOptionName::Components))
+            .to(be_some())
+            .to(equal(&vec!["main", "contrib"].into()));
+
+        expect!(options.get(OptionName::Custom("X-Custom".into())))
+            .to(be_some())
+            .to(equal(&"value".into()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_multiline_value_in_deb822_file() -> eyre::Result<()> {
+        let file = "\
+Types: deb
+Signed-By:
+ -----BEGIN PGP PUBLIC KEY BLOCK-----
+ .
+ -----END PGP PUBLIC KEY BLOCK-----
+Suites: suite
+";
+
+        let entries = parse_deb822_file(file.as_bytes(), true)?;
+
+        expect!(entries.clone()).to(have_len(1));
+
+        let options = match &entries[0] {
+            Deb822Entry::Entry(options) => options,
+            _ => unreachable!(),
+        };
+
+        expect!(options.get(KnownOptionName::SignedBy))
+            .to(be_some())
+            .to(equal(&OptionValue::Multiline(vec![
+                "-----BEGIN PGP PUBLIC KEY BLOCK-----".into(),
+                String::new(),
+                "-----END PGP PUBLIC KEY BLOCK-----".into(),
+            ])));
+
+        expect!(options.get(KnownOptionName::Suites))
+            .to(be_some())
+            .to(equal(&"suite".into()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_fails_on_malformed_deb822_field() {
+        let file = "Types: deb\nnot a field\n";
+
+        expect!(parse_deb822_file(file.as_bytes(), true))
+            .to(be_err())
+            .map(|err| err.downcast::<Error>())
+            .to(be_ok())
+            .to(match_pattern(pattern!(Error::MalformedDeb822Entry { .. })));
+    }
 }
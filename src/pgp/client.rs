@@ -1,7 +1,6 @@
+use std::fmt;
 use std::path::Path;
 
-use reqwest::Url;
-
 /// The encoding of a PGP key.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyEncoding {
@@ -9,6 +8,19 @@ pub enum KeyEncoding {
     Binary,
 }
 
+/// Which parts of a PGP key to export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyExportMode {
+    /// Export the key with all of its signatures.
+    Full,
+
+    /// Export only the primary key, its valid subkeys, and their self-signatures.
+    ///
+    /// This strips third-party certifications, which can make keys fetched from keyservers much
+    /// smaller.
+    Minimal,
+}
+
 /// A PGP key.
 #[derive(Debug, Clone)]
 pub struct Key {
@@ -27,13 +39,28 @@ impl AsRef<[u8]> for Key {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KeyId(String);
 
 impl KeyId {
     pub fn new(id: String) -> Self {
         Self(id)
     }
+
+    /// Return whether this key ID identifies the key with the given fingerprint.
+    ///
+    /// This key ID can be either a full fingerprint or a long key ID.
+    pub fn matches(&self, fingerprint: &Fingerprint) -> bool {
+        let id = self.0.to_uppercase();
+
+        // The key ID of a v6 key is the first 64 bits of its fingerprint, while the key ID of a v4
+        // key is the last 64 bits.
+        if fingerprint.0.len() == Fingerprint::V6_LEN {
+            fingerprint.0.starts_with(&id)
+        } else {
+            fingerprint.0.ends_with(&id)
+        }
+    }
 }
 
 impl AsRef<str> for KeyId {
@@ -42,13 +69,181 @@ impl AsRef<str> for KeyId {
     }
 }
 
+/// The fingerprint of a PGP key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(String);
+
+impl Fingerprint {
+    /// The length of a v4 key fingerprint in hex characters.
+    pub const V4_LEN: usize = 40;
+
+    /// The length of a v6 key fingerprint in hex characters.
+    pub const V6_LEN: usize = 64;
+
+    pub fn new(fingerprint: String) -> Self {
+        Self(fingerprint.to_uppercase())
+    }
+}
+
+impl AsRef<str> for Fingerprint {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Information about a primary key and its subkeys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    /// The fingerprint of the primary key.
+    pub fingerprint: Fingerprint,
+
+    /// The fingerprints of the subkeys.
+    pub subkeys: Vec<Fingerprint>,
+
+    /// The user IDs of the key.
+    pub user_ids: Vec<String>,
+
+    /// When the primary key expires, in seconds since the Unix epoch.
+    pub expires: Option<u64>,
+}
+
+/// The public key algorithm of a key or subkey.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyAlgorithm {
+    Rsa {
+        bits: u32,
+    },
+    Dsa {
+        bits: u32,
+    },
+    Elgamal {
+        bits: u32,
+    },
+
+    /// An elliptic curve algorithm, identified by its curve.
+    Ecc {
+        curve: String,
+    },
+
+    /// An algorithm identified by its OpenPGP algorithm ID.
+    Other {
+        id: u32,
+    },
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // These are the names GnuPG and apt use for these algorithms.
+        match self {
+            Self::Rsa { bits } => write!(f, "rsa{}", bits),
+            Self::Dsa { bits } => write!(f, "dsa{}", bits),
+            Self::Elgamal { bits } => write!(f, "elg{}", bits),
+            Self::Ecc { curve } => f.write_str(curve),
+            Self::Other { id } => write!(f, "algorithm {}", id),
+        }
+    }
+}
+
+/// The hash algorithm used to make a signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Ripemd160,
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha224,
+
+    /// A hash algorithm identified by its OpenPGP algorithm ID.
+    Other {
+        id: u32,
+    },
+}
+
+impl From<u32> for HashAlgorithm {
+    fn from(id: u32) -> Self {
+        match id {
+            1 => Self::Md5,
+            2 => Self::Sha1,
+            3 => Self::Ripemd160,
+            8 => Self::Sha256,
+            9 => Self::Sha384,
+            10 => Self::Sha512,
+            11 => Self::Sha224,
+            id => Self::Other { id },
+        }
+    }
+}
+
+/// The cryptographic parameters of a primary key or subkey.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyParameters {
+    /// The fingerprint of the key.
+    pub fingerprint: Fingerprint,
+
+    /// The public key algorithm of the key.
+    pub algorithm: KeyAlgorithm,
+
+    /// Whether the key can make signatures.
+    pub can_sign: bool,
+
+    /// The hash algorithms of the key's current self-signatures.
+    ///
+    /// For primary keys, these are the newest signatures binding each of its user IDs. For
+    /// subkeys, this is the newest signature binding it to the primary key.
+    pub self_signature_hashes: Vec<HashAlgorithm>,
+}
+
 pub trait PgpClient {
     /// Read a PGP key from a file.
     fn read_key(&self, path: &Path, encoding: KeyEncoding) -> eyre::Result<Key>;
 
-    /// Download a PGP key from a URL.
-    fn download_key(&self, url: &Url, encoding: KeyEncoding) -> eyre::Result<Key>;
+    /// Load a PGP key from its raw bytes.
+    ///
+    /// The `name` describes where the key came from, for error messages.
+    fn load_key(&self, key: Vec<u8>, name: &str, encoding: KeyEncoding) -> eyre::Result<Key>;
+
+    /// Receive a PGP key from the first keyserver in `keyservers` that has it.
+    fn recv_key(
+        &self,
+        keyservers: &[String],
+        id: KeyId,
+        encoding: KeyEncoding,
+    ) -> eyre::Result<Key>;
+
+    /// Return the fingerprints of all the keys and subkeys in `key`.
+    fn fingerprints(&self, key: &Key) -> eyre::Result<Vec<Fingerprint>>;
+
+    /// Return information about each of the primary keys in `key`.
+    fn key_info(&self, key: &Key) -> eyre::Result<Vec<KeyInfo>>;
+
+    /// Return the cryptographic parameters of each of the keys and subkeys in `key`.
+    fn key_parameters(&self, key: &Key) -> eyre::Result<Vec<KeyParameters>>;
+
+    /// Combine `keys` into a single keyring.
+    fn combine_keys(
+        &self,
+        keys: &[Key],
+        encoding: KeyEncoding,
+        mode: KeyExportMode,
+    ) -> eyre::Result<Key>;
+
+    /// Return only the primary keys in `key` with these `fingerprints`, along with their subkeys.
+    fn select_keys(
+        &self,
+        key: &Key,
+        fingerprints: &[Fingerprint],
+        encoding: KeyEncoding,
+    ) -> eyre::Result<Key>;
 
-    /// Receive a PGP key from a keyserver.
-    fn recv_key(&self, keyserver: &str, id: KeyId, encoding: KeyEncoding) -> eyre::Result<Key>;
+    /// Return the fingerprints of the primary keys in `key` which made valid signatures on the
+    /// clearsigned file at `path`.
+    fn verify_signers(&self, key: &Key, path: &Path) -> eyre::Result<Vec<Fingerprint>>;
 }
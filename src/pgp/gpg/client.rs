@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
 use std::process::{Command, Stdio};
 use std::sync::OnceLock;
@@ -9,10 +9,13 @@ use regex::Regex;
 use reqwest::Url;
 
 use crate::error::Error;
-use crate::pgp::{Key, KeyEncoding, KeyId, PgpClient};
+use crate::pgp::{
+    Fingerprint, Key, KeyEncoding, KeyExportMode, KeyId, KeyInfo, KeyParameters, PgpClient,
+};
+use crate::proxy::{ProxyConfig, ProxySetting};
 use crate::stdio::write_stdin;
 
-use super::net::download_file;
+use super::keyring::Keyring;
 
 static PGP_ARMOR_REGEX: OnceLock<Regex> = OnceLock::new();
 
@@ -26,6 +29,8 @@ fn pgp_armor_regex() -> &'static Regex {
 #[derive(Debug, Clone)]
 pub struct GnupgClient {
     command: String,
+    proxy: ProxyConfig,
+    client_cert: bool,
 }
 
 impl GnupgClient {
@@ -33,6 +38,49 @@ impl GnupgClient {
     pub fn new(command: impl Into<String>) -> Self {
         Self {
             command: command.into(),
+            proxy: ProxyConfig::default(),
+            client_cert: false,
+        }
+    }
+
+    /// Access keyservers through the proxies in `proxy`.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Skip keyservers accessed over TLS, because the user configured a client certificate which
+    /// GnuPG wouldn't present.
+    pub fn with_client_cert(mut self, client_cert: bool) -> Self {
+        self.client_cert = client_cert;
+        self
+    }
+
+    /// Return why we can't connect to `keyserver` the way the user asked, if we can't.
+    ///
+    /// GnuPG connects to keyservers through dirmngr, which doesn't support client certificates.
+    pub(super) fn unsupported_keyserver_reason(&self, keyserver: &str) -> Option<String> {
+        let uses_tls = Url::parse(keyserver)
+            .map(|url| matches!(url.scheme(), "hkps" | "https"))
+            .unwrap_or(false);
+
+        if self.client_cert && uses_tls {
+            return Some(String::from(
+                "Skipped, because GnuPG can't present a client certificate to keyservers.",
+            ));
+        }
+
+        None
+    }
+
+    /// Return the proxy to pass to GnuPG for `keyserver`, if there is one.
+    ///
+    /// GnuPG has no way to bypass a proxy configured in `dirmngr.conf`, so connecting directly
+    /// means not passing a proxy.
+    pub(super) fn keyserver_proxy(&self, keyserver: &str) -> Option<Url> {
+        match self.proxy.get(&Url::parse(keyserver).ok()?)? {
+            ProxySetting::Url(url) => Some(url.clone()),
+            ProxySetting::Direct => None,
         }
     }
 
@@ -92,25 +140,42 @@ impl GnupgClient {
     }
 }
 
+impl GnupgClient {
+    /// Create a new keyring containing `key`.
+    fn keyring_with(&self, key: &Key) -> eyre::Result<Keyring> {
+        let mut keyring = self.new_keyring().wrap_err("failed creating keyring")?;
+
+        let current_encoding = self
+            .probe_key_encoding(key.as_ref())
+            .wrap_err("failed probing PGP key encoding")?;
+
+        keyring
+            .import(&mut self.new_key(key.as_ref().to_vec(), current_encoding, None)?)
+            .wrap_err("failed importing key into keyring")?;
+
+        Ok(keyring)
+    }
+}
+
 impl PgpClient for GnupgClient {
     fn read_key(&self, path: &Path, encoding: KeyEncoding) -> eyre::Result<Key> {
         let mut file = File::open(path).wrap_err("failed opening local key file for reading")?;
 
-        file.seek(SeekFrom::Start(0))?;
+        let mut key = Vec::new();
+
+        file.read_to_end(&mut key)
+            .wrap_err("failed reading key from file")?;
+
+        self.load_key(key, &path.to_string_lossy(), encoding)
+    }
 
-        if !self.is_pgp_key(&file)? {
+    fn load_key(&self, key: Vec<u8>, name: &str, encoding: KeyEncoding) -> eyre::Result<Key> {
+        if !self.is_pgp_key(key.as_slice())? {
             bail!(Error::NotPgpKey {
-                key: path.to_string_lossy().to_string(),
+                key: name.to_string(),
             });
         }
 
-        file.seek(SeekFrom::Start(0))?;
-
-        let mut key = Vec::new();
-
-        file.read_to_end(&mut key)
-            .wrap_err("failed reading key from vile")?;
-
         let current_encoding = self
             .probe_key_encoding(key.as_slice())
             .wrap_err("failed probing PGP key encoding")?;
@@ -125,61 +190,137 @@ impl PgpClient for GnupgClient {
         Ok(Key::new(key_bytes))
     }
 
-    fn download_key(&self, url: &Url, encoding: KeyEncoding) -> eyre::Result<Key> {
-        let mut file = download_file(url).wrap_err("failed downloading PGP key")?;
+    fn recv_key(
+        &self,
+        keyservers: &[String],
+        id: KeyId,
+        encoding: KeyEncoding,
+    ) -> eyre::Result<Key> {
+        let mut keyring = self.new_keyring().wrap_err("failed creating keyring")?;
 
-        file.seek(SeekFrom::Start(0))?;
+        let keyring_key = keyring
+            .recv_key(keyservers, id)
+            .wrap_err("failed getting signing key from keyserver")?;
 
-        if !self.is_pgp_key(&file)? {
-            bail!(Error::NotPgpKey {
-                key: url.to_string(),
-            });
-        }
+        let key = keyring
+            .export(keyring_key, encoding, KeyExportMode::Full)
+            .wrap_err("failed exporting signing key from keyring")?;
 
-        file.seek(SeekFrom::Start(0))?;
+        Ok(Key::new(key.into_bytes()))
+    }
 
-        let mut key = Vec::new();
+    fn fingerprints(&self, key: &Key) -> eyre::Result<Vec<Fingerprint>> {
+        let current_encoding = self
+            .probe_key_encoding(key.as_ref())
+            .wrap_err("failed probing PGP key encoding")?;
 
-        file.read_to_end(&mut key)
-            .wrap_err("failed reading key from vile")?;
+        self.new_key(key.as_ref().to_vec(), current_encoding, None)?
+            .fingerprints()
+    }
 
+    fn key_info(&self, key: &Key) -> eyre::Result<Vec<KeyInfo>> {
         let current_encoding = self
-            .probe_key_encoding(key.as_slice())
+            .probe_key_encoding(key.as_ref())
             .wrap_err("failed probing PGP key encoding")?;
 
-        let key = self.new_key(key, current_encoding, None)?;
+        self.new_key(key.as_ref().to_vec(), current_encoding, None)?
+            .info()
+    }
 
-        let key_bytes = match encoding {
-            KeyEncoding::Armored => key.enarmor()?.into_bytes(),
-            KeyEncoding::Binary => key.dearmor()?.into_bytes(),
-        };
+    fn key_parameters(&self, key: &Key) -> eyre::Result<Vec<KeyParameters>> {
+        let current_encoding = self
+            .probe_key_encoding(key.as_ref())
+            .wrap_err("failed probing PGP key encoding")?;
 
-        Ok(Key::new(key_bytes))
+        self.new_key(key.as_ref().to_vec(), current_encoding, None)?
+            .parameters()
     }
 
-    fn recv_key(&self, keyserver: &str, id: KeyId, encoding: KeyEncoding) -> eyre::Result<Key> {
+    fn combine_keys(
+        &self,
+        keys: &[Key],
+        encoding: KeyEncoding,
+        mode: KeyExportMode,
+    ) -> eyre::Result<Key> {
         let mut keyring = self.new_keyring().wrap_err("failed creating keyring")?;
 
-        let keyring_key = keyring
-            .recv_key(keyserver, id)
-            .wrap_err("failed getting signing key from keyserver")?;
+        for key in keys {
+            let current_encoding = self
+                .probe_key_encoding(key.as_ref())
+                .wrap_err("failed probing PGP key encoding")?;
+
+            keyring
+                .import(&mut self.new_key(key.as_ref().to_vec(), current_encoding, None)?)
+                .wrap_err("failed importing key into keyring")?;
+        }
 
         let key = keyring
-            .export(keyring_key, encoding)
-            .wrap_err("failed exporting signing key from keyring")?;
+            .export_all(encoding, mode)
+            .wrap_err("failed exporting keys from keyring")?;
 
         Ok(Key::new(key.into_bytes()))
     }
+
+    fn select_keys(
+        &self,
+        key: &Key,
+        fingerprints: &[Fingerprint],
+        encoding: KeyEncoding,
+    ) -> eyre::Result<Key> {
+        let mut keyring = self.keyring_with(key)?;
+
+        let ids = fingerprints
+            .iter()
+            .map(|fingerprint| KeyId::new(fingerprint.to_string()))
+            .collect::<Vec<_>>();
+
+        let key = keyring
+            .export_ids(&ids, encoding, KeyExportMode::Full)
+            .wrap_err("failed exporting keys from keyring")?;
+
+        Ok(Key::new(key.into_bytes()))
+    }
+
+    fn verify_signers(&self, key: &Key, path: &Path) -> eyre::Result<Vec<Fingerprint>> {
+        self.keyring_with(key)?
+            .verify(path)
+            .wrap_err("failed verifying signature")
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use xpct::{be_err, be_ok, equal, expect};
+    use std::fs;
+    use std::path::Path;
+
+    use xpct::{be_err, be_ok, contain_element, equal, expect};
 
     use crate::error::Error;
+    use crate::pgp::gpg::testing::{generate_key, gpg};
 
     use super::*;
 
+    /// Count the signatures on a key.
+    fn count_signatures(home: &Path, key: &Key) -> eyre::Result<usize> {
+        let mut key_file = tempfile::NamedTempFile::new()?;
+        io::copy(&mut key.as_ref(), &mut key_file)?;
+
+        let output = gpg(
+            home,
+            &[
+                "--show-keys",
+                "--with-colons",
+                "--with-sig-list",
+                &key_file.path().to_string_lossy(),
+            ],
+        )?;
+
+        Ok(String::from_utf8(output)?
+            .lines()
+            .filter(|line| line.starts_with("sig:"))
+            .count())
+    }
+
     #[test]
     fn fails_when_gpg_path_is_nonexistent() -> eyre::Result<()> {
         let key_file = tempfile::NamedTempFile::new()?;
@@ -198,4 +339,191 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn recv_key_reports_every_failed_keyserver() -> eyre::Result<()> {
+        let client = GnupgClient::new("gpg");
+
+        // Nothing should be listening on these ports.
+        let keyservers = vec![
+            String::from("hkp://127.0.0.1:1"),
+            String::from("hkp://127.0.0.1:2"),
+        ];
+
+        expect!(client.recv_key(
+            &keyservers,
+            KeyId::new("D9C5A07BD817FAEF313D6434B2FF242E7718CD4C".into()),
+            KeyEncoding::Binary,
+        ))
+        .to(be_err())
+        .map(|err| err.downcast::<Error>())
+        .to(be_ok())
+        .map(|err| match err {
+            Error::KeyserverFetchFailed { failures, .. } => failures
+                .into_iter()
+                .map(|failure| failure.keyserver)
+                .collect::<Vec<_>>(),
+            _ => Vec::new(),
+        })
+        .to(equal(keyservers.clone()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn recv_key_skips_tls_keyservers_with_client_certificate() -> eyre::Result<()> {
+        let client = GnupgClient::new("gpg").with_client_cert(true);
+
+        // Nothing should be listening on these ports.
+        let keyservers = vec![
+            String::from("hkps://127.0.0.1:1"),
+            String::from("hkp://127.0.0.1:2"),
+        ];
+
+        expect!(client.recv_key(
+            &keyservers,
+            KeyId::new("D9C5A07BD817FAEF313D6434B2FF242E7718CD4C".into()),
+            KeyEncoding::Binary,
+        ))
+        .to(be_err())
+        .map(|err| err.downcast::<Error>())
+        .to(be_ok())
+        .map(|err| match err {
+            Error::KeyserverFetchFailed { failures, .. } => failures,
+            _ => Vec::new(),
+        })
+        .map(|failures| {
+            failures
+                .into_iter()
+                .map(|failure| (failure.keyserver, failure.reason.starts_with("Skipped")))
+                .collect::<Vec<_>>()
+        })
+        .to(equal(vec![
+            (String::from("hkps://127.0.0.1:1"), true),
+            (String::from("hkp://127.0.0.1:2"), false),
+        ]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn minimal_key_strips_third_party_signatures() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+
+        let signing_key = generate_key(home.path(), "Signing Key <signing@example.com>")?;
+        let other_key = generate_key(home.path(), "Other Key <other@example.com>")?;
+
+        gpg(
+            home.path(),
+            &["--local-user", &other_key, "--quick-sign-key", &signing_key],
+        )?;
+
+        let full_key = Key::new(gpg(home.path(), &["--export", &signing_key])?);
+
+        let client = GnupgClient::new("gpg");
+        let minimal_key = client.combine_keys(
+            std::slice::from_ref(&full_key),
+            KeyEncoding::Binary,
+            KeyExportMode::Minimal,
+        )?;
+
+        expect!(count_signatures(home.path(), &full_key))
+            .to(be_ok())
+            .to(equal(2));
+
+        expect!(count_signatures(home.path(), &minimal_key))
+            .to(be_ok())
+            .to(equal(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn combined_key_contains_every_key() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+
+        let old_key = generate_key(home.path(), "Old Key <old@example.com>")?;
+        let new_key = generate_key(home.path(), "New Key <new@example.com>")?;
+
+        let client = GnupgClient::new("gpg");
+
+        let keys = [
+            Key::new(gpg(home.path(), &["--export", &old_key])?),
+            Key::new(gpg(home.path(), &["--armor", "--export", &new_key])?),
+        ];
+
+        let combined_key = client.combine_keys(&keys, KeyEncoding::Binary, KeyExportMode::Full)?;
+
+        expect!(client.fingerprints(&combined_key))
+            .to(be_ok())
+            .map(|fingerprints| {
+                fingerprints
+                    .into_iter()
+                    .map(|fingerprint| fingerprint.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .to(contain_element(old_key))
+            .to(contain_element(new_key));
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_info_includes_user_ids() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+
+        let fingerprint = generate_key(home.path(), "Repo Key: Test <repo@example.com>")?;
+        let key = Key::new(gpg(home.path(), &["--export", &fingerprint])?);
+
+        let client = GnupgClient::new("gpg");
+
+        expect!(client.key_info(&key))
+            .to(be_ok())
+            .to(equal(vec![KeyInfo {
+                fingerprint: Fingerprint::new(fingerprint),
+                subkeys: Vec::new(),
+                user_ids: vec!["Repo Key: Test <repo@example.com>".into()],
+                expires: None,
+            }]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_signers_returns_primary_key_of_valid_signature() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+        let client = GnupgClient::new("gpg");
+
+        let signer = generate_key(home.path(), "signer <signer@example.com>")?;
+        let other = generate_key(home.path(), "other <other@example.com>")?;
+
+        let signed_path = home.path().join("InRelease");
+        let message_path = home.path().join("Release");
+        fs::write(&message_path, "Origin: Example\n")?;
+
+        gpg(
+            home.path(),
+            &[
+                "--local-user",
+                &signer,
+                "--output",
+                &signed_path.to_string_lossy(),
+                "--clearsign",
+                &message_path.to_string_lossy(),
+            ],
+        )?;
+
+        let signer_key = Key::new(gpg(home.path(), &["--export", &signer])?);
+        let other_key = Key::new(gpg(home.path(), &["--export", &other])?);
+
+        expect!(client.verify_signers(&signer_key, &signed_path))
+            .to(be_ok())
+            .to(equal(vec![Fingerprint::new(signer)]));
+
+        expect!(client.verify_signers(&other_key, &signed_path))
+            .to(be_ok())
+            .to(equal(Vec::new()));
+
+        Ok(())
+    }
 }
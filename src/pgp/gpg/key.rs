@@ -3,20 +3,61 @@ use std::process::Stdio;
 
 use eyre::{bail, WrapErr};
 
-use crate::pgp::{KeyEncoding, KeyId};
+use crate::pgp::{
+    Fingerprint, HashAlgorithm, KeyAlgorithm, KeyEncoding, KeyExportMode, KeyId, KeyInfo,
+    KeyParameters,
+};
 use crate::stdio::{read_stderr, read_stdout, wait, write_stdin};
 
 use super::client::GnupgClient;
 
 /// The machine-readable output of a GnuPG command.
 #[derive(Debug)]
-struct ColonOutput {
+pub(super) struct ColonOutput {
     lines: Vec<Vec<String>>,
 }
 
+/// Decode the `\xNN` escape sequences gpg uses for special characters in colon output.
+fn unescape_colon_field(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = match tail {
+            [b'x', high, low, ..] if byte == b'\\' => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(escaped_byte) => {
+                bytes.push(escaped_byte);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
 impl ColonOutput {
     const RECORD_TYPE_INDEX: usize = 0;
+    const VALIDITY_INDEX: usize = 1;
+    const KEY_LENGTH_INDEX: usize = 2;
+    const ALGORITHM_INDEX: usize = 3;
     const KEY_ID_INDEX: usize = 4;
+    const CREATION_DATE_INDEX: usize = 5;
+    const EXPIRATION_DATE_INDEX: usize = 6;
+    const USER_ID_INDEX: usize = 9;
+    const SIGNATURE_CLASS_INDEX: usize = 10;
+    const CAPABILITIES_INDEX: usize = 11;
+    const HASH_ALGORITHM_INDEX: usize = 15;
+    const CURVE_NAME_INDEX: usize = 16;
 
     /// Create a new instance from a gpg command's stdout.
     pub fn new(output: &[u8]) -> eyre::Result<Self> {
@@ -51,6 +92,226 @@ impl ColonOutput {
 
         bail!("could not find public key record in gpg colon output");
     }
+
+    /// Get the fingerprints of all the keys and subkeys.
+    pub fn fingerprints(&self) -> eyre::Result<Vec<Fingerprint>> {
+        let mut fingerprints = Vec::new();
+
+        for line in &self.lines {
+            if line.get(Self::RECORD_TYPE_INDEX).map(String::as_str) != Some("fpr") {
+                continue;
+            }
+
+            // In `fpr` records, the fingerprint is stored in the user ID field.
+            match line.get(Self::USER_ID_INDEX) {
+                Some(fingerprint) => fingerprints.push(Fingerprint::new(fingerprint.to_string())),
+                None => bail!("could not find fingerprint in gpg colon output"),
+            }
+        }
+
+        Ok(fingerprints)
+    }
+
+    /// Get information about each of the primary keys.
+    pub fn key_info(&self) -> eyre::Result<Vec<KeyInfo>> {
+        let mut infos: Vec<KeyInfo> = Vec::new();
+
+        // The record type of the last `pub` or `sub` record, which the following `fpr` record
+        // belongs to.
+        let mut last_key_record = None;
+
+        for line in &self.lines {
+            let field = |index: usize| line.get(index).map(String::as_str).unwrap_or_default();
+
+            match field(Self::RECORD_TYPE_INDEX) {
+                "pub" => {
+                    let expires = match field(Self::EXPIRATION_DATE_INDEX) {
+                        "" => None,
+                        timestamp => Some(
+                            timestamp
+                                .parse::<u64>()
+                                .wrap_err("invalid expiration date in gpg colon output")?,
+                        ),
+                    };
+
+                    infos.push(KeyInfo {
+                        fingerprint: Fingerprint::new(String::new()),
+                        subkeys: Vec::new(),
+                        user_ids: Vec::new(),
+                        expires,
+                    });
+
+                    last_key_record = Some("pub");
+                }
+                "sub" => last_key_record = Some("sub"),
+                "fpr" => {
+                    let (Some(info), Some(record_type)) =
+                        (infos.last_mut(), last_key_record.take())
+                    else {
+                        continue;
+                    };
+
+                    // In `fpr` records, the fingerprint is stored in the user ID field.
+                    let fingerprint = Fingerprint::new(field(Self::USER_ID_INDEX).to_string());
+
+                    if record_type == "pub" {
+                        info.fingerprint = fingerprint;
+                    } else {
+                        info.subkeys.push(fingerprint);
+                    }
+                }
+                "uid" => {
+                    if let Some(info) = infos.last_mut() {
+                        info.user_ids
+                            .push(unescape_colon_field(field(Self::USER_ID_INDEX)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if infos
+            .iter()
+            .any(|info| info.fingerprint.as_ref().is_empty())
+        {
+            bail!("could not find fingerprint of primary key in gpg colon output");
+        }
+
+        Ok(infos)
+    }
+
+    /// Parse the public key algorithm of a `pub` or `sub` record.
+    fn key_algorithm(line: &[String]) -> eyre::Result<KeyAlgorithm> {
+        let field = |index: usize| line.get(index).map(String::as_str).unwrap_or_default();
+
+        let bits = || {
+            field(Self::KEY_LENGTH_INDEX)
+                .parse::<u32>()
+                .wrap_err("invalid key length in gpg colon output")
+        };
+
+        let id = field(Self::ALGORITHM_INDEX)
+            .parse::<u32>()
+            .wrap_err("invalid public key algorithm in gpg colon output")?;
+
+        // These are the OpenPGP public key algorithm IDs.
+        Ok(match (id, field(Self::CURVE_NAME_INDEX)) {
+            (1..=3, _) => KeyAlgorithm::Rsa { bits: bits()? },
+            (16 | 20, _) => KeyAlgorithm::Elgamal { bits: bits()? },
+            (17, _) => KeyAlgorithm::Dsa { bits: bits()? },
+            (_, "") => KeyAlgorithm::Other { id },
+            (_, curve) => KeyAlgorithm::Ecc {
+                curve: curve.to_string(),
+            },
+        })
+    }
+
+    /// Add the hash algorithm of the `newest` self-signature of a user ID or subkey to `key`.
+    fn push_newest_self_signature(
+        key: Option<&mut KeyParameters>,
+        newest: &mut Option<(u64, HashAlgorithm)>,
+    ) {
+        if let (Some(key), Some((_, hash))) = (key, newest.take()) {
+            key.self_signature_hashes.push(hash);
+        }
+    }
+
+    /// Get the cryptographic parameters of each of the keys and subkeys.
+    ///
+    /// This needs the output of `--with-sig-list` to find the self-signatures. Only the newest
+    /// self-signature on each user ID or subkey is used, since that's the one which binds it.
+    /// Revoked and expired subkeys are left out, since they can't be used to check signatures.
+    pub fn key_parameters(&self) -> eyre::Result<Vec<KeyParameters>> {
+        let mut keys: Vec<KeyParameters> = Vec::new();
+
+        // The key ID of the current primary key, which issues its self-signatures.
+        let mut primary_key_id = "";
+
+        // Whether the current subkey is revoked or expired, so its records are skipped.
+        let mut skip_subkey = false;
+
+        // The creation date and hash algorithm of the newest self-signature on the current user
+        // ID or subkey.
+        let mut newest_self_signature = None;
+
+        for line in &self.lines {
+            let field = |index: usize| line.get(index).map(String::as_str).unwrap_or_default();
+
+            let record_type = field(Self::RECORD_TYPE_INDEX);
+
+            if matches!(record_type, "pub" | "sub" | "uid" | "uat") {
+                Self::push_newest_self_signature(keys.last_mut(), &mut newest_self_signature);
+            }
+
+            match record_type {
+                record_type @ ("pub" | "sub") => {
+                    if record_type == "pub" {
+                        primary_key_id = field(Self::KEY_ID_INDEX);
+                    }
+
+                    skip_subkey =
+                        record_type == "sub" && matches!(field(Self::VALIDITY_INDEX), "r" | "e");
+
+                    if skip_subkey {
+                        continue;
+                    }
+
+                    keys.push(KeyParameters {
+                        fingerprint: Fingerprint::new(String::new()),
+                        algorithm: Self::key_algorithm(line)?,
+                        // Lowercase letters are the capabilities of this key, while uppercase
+                        // letters are the capabilities of the primary key and all its subkeys.
+                        can_sign: field(Self::CAPABILITIES_INDEX).contains('s'),
+                        self_signature_hashes: Vec::new(),
+                    });
+                }
+                _ if skip_subkey => {}
+                "fpr" => {
+                    if let Some(key) = keys.last_mut() {
+                        if key.fingerprint.as_ref().is_empty() {
+                            // In `fpr` records, the fingerprint is stored in the user ID field.
+                            key.fingerprint =
+                                Fingerprint::new(field(Self::USER_ID_INDEX).to_string());
+                        }
+                    }
+                }
+                "sig" => {
+                    // Signature classes starting with 1 are certifications and binding
+                    // signatures, while those starting with 2 or 3 are revocations.
+                    let is_self_signature = field(Self::KEY_ID_INDEX) == primary_key_id
+                        && field(Self::SIGNATURE_CLASS_INDEX).starts_with('1');
+
+                    if !is_self_signature {
+                        continue;
+                    }
+
+                    let created = field(Self::CREATION_DATE_INDEX)
+                        .parse::<u64>()
+                        .wrap_err("invalid signature creation date in gpg colon output")?;
+
+                    let hash = field(Self::HASH_ALGORITHM_INDEX)
+                        .parse::<u32>()
+                        .wrap_err("invalid hash algorithm in gpg colon output")?;
+
+                    if newest_self_signature
+                        .as_ref()
+                        .map_or(true, |(newest, _)| created >= *newest)
+                    {
+                        newest_self_signature = Some((created, HashAlgorithm::from(hash)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self::push_newest_self_signature(keys.last_mut(), &mut newest_self_signature);
+
+        if keys.iter().any(|key| key.fingerprint.as_ref().is_empty()) {
+            bail!("could not find fingerprint of key in gpg colon output");
+        }
+
+        Ok(keys)
+    }
 }
 
 impl GnupgClient {
@@ -129,21 +390,25 @@ impl GnupgKey {
             .wrap_err("failed importing key into keyring")?;
 
         keyring
-            .export(keyring_key, KeyEncoding::Armored)
+            .export(keyring_key, KeyEncoding::Armored, KeyExportMode::Full)
             .wrap_err("failed exporting key from keyring")
     }
 
-    /// Return the key's key ID.
-    pub fn id(&mut self) -> eyre::Result<KeyId> {
-        if let Some(id) = &self.id {
-            return Ok(id.clone());
-        }
-
+    /// Return the machine-readable output of `gpg --show-keys` for this key.
+    ///
+    /// If `with_signatures` is set, this includes the signatures on each key.
+    fn show_keys(&self, with_signatures: bool) -> eyre::Result<ColonOutput> {
         let mut process = self
             .client
             .command()
             .arg("--show-keys")
             .arg("--with-colons")
+            .arg("--with-fingerprint")
+            .args(if with_signatures {
+                vec!["--with-sig-list"]
+            } else {
+                Vec::new()
+            })
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -159,7 +424,17 @@ impl GnupgKey {
 
         let command_output = stdout_handle.join()?;
 
-        let key_id = ColonOutput::new(&command_output)?
+        ColonOutput::new(&command_output)
+    }
+
+    /// Return the key's key ID.
+    pub fn id(&mut self) -> eyre::Result<KeyId> {
+        if let Some(id) = &self.id {
+            return Ok(id.clone());
+        }
+
+        let key_id = self
+            .show_keys(false)?
             .public_key_id()
             .wrap_err("failed parsing gpg output")?;
 
@@ -168,6 +443,27 @@ impl GnupgKey {
         Ok(key_id)
     }
 
+    /// Return the fingerprints of all the keys and subkeys in this key.
+    pub fn fingerprints(&self) -> eyre::Result<Vec<Fingerprint>> {
+        self.show_keys(false)?
+            .fingerprints()
+            .wrap_err("failed parsing gpg output")
+    }
+
+    /// Return information about each of the primary keys in this key.
+    pub fn info(&self) -> eyre::Result<Vec<KeyInfo>> {
+        self.show_keys(false)?
+            .key_info()
+            .wrap_err("failed parsing gpg output")
+    }
+
+    /// Return the cryptographic parameters of each of the keys and subkeys in this key.
+    pub fn parameters(&self) -> eyre::Result<Vec<KeyParameters>> {
+        self.show_keys(true)?
+            .key_parameters()
+            .wrap_err("failed parsing gpg output")
+    }
+
     /// Consume this key and return its bytes.
     pub fn into_bytes(self) -> Vec<u8> {
         self.bytes
@@ -1,15 +1,44 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 use std::process::Stdio;
 
 use eyre::{bail, WrapErr};
-use tempfile::NamedTempFile;
+use reqwest::Url;
+use tempfile::{NamedTempFile, TempDir};
 
-use crate::error::Error;
-use crate::pgp::{KeyEncoding, KeyId};
+use crate::error::{Error, KeyserverFailure};
+use crate::pgp::{Fingerprint, KeyEncoding, KeyExportMode, KeyId};
 use crate::stdio::{read_stderr, read_stdout, wait, write_stdin};
 
-use super::key::GnupgKey;
+use super::key::{ColonOutput, GnupgKey};
 use super::GnupgClient;
 
+/// The prefix of the status line GnuPG prints for each valid signature.
+const VALIDSIG_STATUS_PREFIX: &str = "[GNUPG:] VALIDSIG ";
+
+/// The index of the primary key fingerprint in a `VALIDSIG` status line, after the prefix.
+const PRIMARY_KEY_FINGERPRINT_INDEX: usize = 9;
+
+/// The name of the options file in the directory returned by [`keyserver_options_dir`].
+const OPTIONS_FILE_NAME: &str = "gpg.conf";
+
+/// Create a directory with a GnuPG options file which makes it access keyservers through `proxy`.
+///
+/// Temporary directories are only accessible by their owner, which gpg requires of the
+/// directories containing its options files.
+fn keyserver_options_dir(proxy: &Url) -> eyre::Result<TempDir> {
+    let dir = tempfile::tempdir().wrap_err("failed creating temporary gpg options directory")?;
+
+    let mut file = File::create(dir.path().join(OPTIONS_FILE_NAME))
+        .wrap_err("failed creating temporary gpg options file")?;
+
+    writeln!(file, "keyserver-options http-proxy={}", proxy)
+        .wrap_err("failed writing temporary gpg options file")?;
+
+    Ok(dir)
+}
+
 /// A PGP key in a keyring.
 #[derive(Debug)]
 pub struct KeyringKey {
@@ -34,30 +63,118 @@ pub struct Keyring {
 }
 
 impl Keyring {
-    /// Import a key into this keyring from a keyserver.
-    pub fn recv_key(&mut self, keyserver: &str, id: KeyId) -> eyre::Result<KeyringKey> {
-        let output = self
+    /// Import a key into this keyring from the first keyserver in `keyservers` that has it.
+    ///
+    /// If every keyserver fails, the error includes the reason each one failed.
+    pub fn recv_key(&mut self, keyservers: &[String], id: KeyId) -> eyre::Result<KeyringKey> {
+        let mut failures = Vec::with_capacity(keyservers.len());
+
+        for keyserver in keyservers {
+            if let Some(reason) = self.client.unsupported_keyserver_reason(keyserver) {
+                failures.push(KeyserverFailure {
+                    keyserver: keyserver.to_owned(),
+                    reason,
+                });
+
+                continue;
+            }
+
+            let mut command = self.client.command();
+
+            // The proxy URL can contain credentials, which anyone could see in the arguments of
+            // the process, so it goes in an options file only we can read instead. This replaces
+            // gpg.conf for this command, but every option we need is passed explicitly.
+            let options_dir = match self.client.keyserver_proxy(keyserver) {
+                Some(proxy) => Some(keyserver_options_dir(&proxy)?),
+                None => None,
+            };
+
+            if let Some(options_dir) = &options_dir {
+                command
+                    .arg("--options")
+                    .arg(options_dir.path().join(OPTIONS_FILE_NAME));
+            }
+
+            command
+                .arg("--no-default-keyring")
+                .arg("--keyring")
+                .arg(self.file.path().as_os_str())
+                .arg("--keyserver")
+                .arg(keyserver);
+
+            let output = command
+                .arg("--recv-keys")
+                .arg(id.as_ref())
+                .output()
+                .map_err(|err| self.client.map_err(err))?;
+
+            if output.status.success() {
+                self.verify_fingerprint(&id)?;
+
+                return Ok(KeyringKey { id });
+            }
+
+            failures.push(KeyserverFailure {
+                keyserver: keyserver.to_owned(),
+                reason: String::from_utf8(output.stderr)
+                    .wrap_err("failed to decode gpg command stderr")?
+                    .trim()
+                    .to_string(),
+            });
+        }
+
+        bail!(Error::KeyserverFetchFailed {
+            id: id.as_ref().to_string(),
+            failures,
+        });
+    }
+
+    /// Return the fingerprints of all the keys and subkeys in this keyring.
+    pub fn fingerprints(&self) -> eyre::Result<Vec<Fingerprint>> {
+        let mut process = self
             .client
             .command()
             .arg("--no-default-keyring")
             .arg("--keyring")
             .arg(self.file.path().as_os_str())
-            .arg("--keyserver")
-            .arg(keyserver)
-            .arg("--recv-keys")
-            .arg(id.as_ref())
-            .output()
+            .arg("--list-keys")
+            .arg("--with-colons")
+            .arg("--with-fingerprint")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
             .map_err(|err| self.client.map_err(err))?;
 
-        if !output.status.success() {
-            bail!(Error::KeyserverFetchFailed {
+        let stdout_handle = read_stdout(&mut process);
+        let stderr_handle = read_stderr(&mut process);
+
+        wait(process, stderr_handle)?;
+
+        ColonOutput::new(&stdout_handle.join()?)?
+            .fingerprints()
+            .wrap_err("failed parsing gpg output")
+    }
+
+    /// Return an error if this keyring doesn't contain a key matching `id`.
+    ///
+    /// Keyservers are untrusted, so we need to make sure the key we got back is the one we asked
+    /// for before we export it.
+    fn verify_fingerprint(&self, id: &KeyId) -> eyre::Result<()> {
+        let fingerprints = self
+            .fingerprints()
+            .wrap_err("failed getting fingerprints of keys in keyring")?;
+
+        if !fingerprints
+            .iter()
+            .any(|fingerprint| id.matches(fingerprint))
+        {
+            bail!(Error::KeyFingerprintMismatch {
                 id: id.as_ref().to_string(),
-                reason: String::from_utf8(output.stderr)
-                    .wrap_err("failed to decode gpg command stderr")?,
+                fingerprints: fingerprints.iter().map(ToString::to_string).collect(),
             });
         }
 
-        Ok(KeyringKey { id })
+        Ok(())
     }
 
     /// Import a key into this keyring.
@@ -85,7 +202,93 @@ impl Keyring {
     }
 
     /// Export a key from this keyring.
-    pub fn export(&mut self, key: KeyringKey, encoding: KeyEncoding) -> eyre::Result<GnupgKey> {
+    pub fn export(
+        &mut self,
+        key: KeyringKey,
+        encoding: KeyEncoding,
+        mode: KeyExportMode,
+    ) -> eyre::Result<GnupgKey> {
+        let key_bytes = self.export_keys(std::slice::from_ref(&key.id), encoding, mode)?;
+
+        self.client.new_key(key_bytes, encoding, Some(key.id))
+    }
+
+    /// Export every key in this keyring.
+    pub fn export_all(
+        &mut self,
+        encoding: KeyEncoding,
+        mode: KeyExportMode,
+    ) -> eyre::Result<GnupgKey> {
+        let key_bytes = self.export_keys(&[], encoding, mode)?;
+
+        self.client.new_key(key_bytes, encoding, None)
+    }
+
+    /// Export the keys with the given `ids`, which must all be in this keyring.
+    pub fn export_ids(
+        &mut self,
+        ids: &[KeyId],
+        encoding: KeyEncoding,
+        mode: KeyExportMode,
+    ) -> eyre::Result<GnupgKey> {
+        for id in ids {
+            self.verify_fingerprint(id)?;
+        }
+
+        // Exporting no IDs would export every key.
+        if ids.is_empty() {
+            return self.client.new_key(Vec::new(), encoding, None);
+        }
+
+        let key_bytes = self.export_keys(ids, encoding, mode)?;
+
+        self.client.new_key(key_bytes, encoding, None)
+    }
+
+    /// Return the fingerprints of the primary keys in this keyring which made valid signatures on
+    /// the clearsigned file at `path`.
+    pub fn verify(&self, path: &Path) -> eyre::Result<Vec<Fingerprint>> {
+        let output = self
+            .client
+            .command()
+            .arg("--no-default-keyring")
+            .arg("--keyring")
+            .arg(self.file.path().as_os_str())
+            .arg("--status-fd")
+            .arg("1")
+            .arg("--verify")
+            .arg(path)
+            .output()
+            .map_err(|err| self.client.map_err(err))?;
+
+        // GnuPG exits with an error when there's no valid signature, so we only go by the status
+        // output, which reports each valid signature on its own line.
+        let status =
+            String::from_utf8(output.stdout).wrap_err("failed to decode gpg command stdout")?;
+
+        let mut signers = status
+            .lines()
+            .filter_map(|line| {
+                line.strip_prefix(VALIDSIG_STATUS_PREFIX)?
+                    .split(' ')
+                    .nth(PRIMARY_KEY_FINGERPRINT_INDEX)
+                    .map(|fingerprint| Fingerprint::new(fingerprint.to_string()))
+            })
+            .collect::<Vec<_>>();
+
+        // A file can be signed by several subkeys of the same primary key.
+        signers.dedup();
+
+        Ok(signers)
+    }
+
+    /// Export the keys with the given `ids`, or every key if `ids` is empty.
+    fn export_keys(
+        &mut self,
+        ids: &[KeyId],
+        encoding: KeyEncoding,
+        mode: KeyExportMode,
+    ) -> eyre::Result<Vec<u8>> {
         let mut process = self
             .client
             .command()
@@ -96,8 +299,12 @@ impl Keyring {
                 KeyEncoding::Binary => Vec::new(),
                 KeyEncoding::Armored => vec!["--armor"],
             })
+            .args(match mode {
+                KeyExportMode::Full => Vec::new(),
+                KeyExportMode::Minimal => vec!["--export-options", "export-minimal,export-clean"],
+            })
             .arg("--export")
-            .arg(key.id.as_ref())
+            .args(ids.iter().map(AsRef::as_ref))
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
@@ -108,8 +315,6 @@ impl Keyring {
 
         wait(process, stderr_handle)?;
 
-        let key_bytes = stdout_handle.join()?;
-
-        self.client.new_key(key_bytes, encoding, Some(key.id))
+        stdout_handle.join()
     }
 }
@@ -1,6 +1,8 @@
 mod client;
 mod key;
 mod keyring;
-mod net;
+
+#[cfg(test)]
+pub mod testing;
 
 pub use client::GnupgClient;
@@ -0,0 +1,120 @@
+//! Helpers for tests which need real PGP keys.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use eyre::{bail, eyre};
+
+/// Run a gpg command against the home directory `home` and return its stdout.
+pub fn gpg(home: &Path, args: &[&str]) -> eyre::Result<Vec<u8>> {
+    let output = Command::new("gpg")
+        .arg("--homedir")
+        .arg(home)
+        .arg("--batch")
+        .arg("--yes")
+        .arg("--pinentry-mode")
+        .arg("loopback")
+        .arg("--passphrase")
+        .arg("")
+        .args(args)
+        .output()?;
+
+    if !output.status.success() {
+        bail!(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+
+    Ok(output.stdout)
+}
+
+/// Generate a new signing key in the home directory `home` and return its fingerprint.
+pub fn generate_key(home: &Path, user_id: &str) -> eyre::Result<String> {
+    gpg(
+        home,
+        &["--quick-gen-key", user_id, "ed25519", "sign", "never"],
+    )?;
+
+    fingerprint(home, user_id)
+}
+
+/// Generate a new signing key in the home directory `home` which apt considers weak, and return
+/// its fingerprint.
+///
+/// This is a 1024-bit RSA key with a SHA-1 self-signature.
+pub fn generate_weak_key(home: &Path, user_id: &str) -> eyre::Result<String> {
+    gpg(
+        home,
+        &[
+            "--cert-digest-algo",
+            "SHA1",
+            "--quick-gen-key",
+            user_id,
+            "rsa1024",
+            "sign",
+            "never",
+        ],
+    )?;
+
+    fingerprint(home, user_id)
+}
+
+/// Generate a new signing key in the home directory `home` whose weak parts apt no longer uses,
+/// and return its fingerprint.
+///
+/// This is a 3072-bit RSA key whose SHA-1 user ID self-signature is superseded by a newer SHA-256
+/// one, and which has an expired 1024-bit RSA signing subkey.
+pub fn generate_superseded_weak_key(home: &Path, user_id: &str) -> eyre::Result<String> {
+    let past = ["--faked-system-time", "20200101T000000"];
+
+    gpg(
+        home,
+        &[
+            &past[..],
+            &["--cert-digest-algo", "SHA1"],
+            &["--quick-gen-key", user_id, "rsa3072", "sign", "never"],
+        ]
+        .concat(),
+    )?;
+
+    let fingerprint = fingerprint(home, user_id)?;
+
+    gpg(
+        home,
+        &[
+            &past[..],
+            &["--quick-add-key", &fingerprint, "rsa1024", "sign", "1d"],
+        ]
+        .concat(),
+    )?;
+
+    // gpg deletes the self-signatures a new one supersedes, so import the old ones back.
+    let old_key = home.join("old-key.gpg");
+    fs::write(&old_key, gpg(home, &["--export", &fingerprint])?)?;
+
+    gpg(
+        home,
+        &[
+            "--cert-digest-algo",
+            "SHA256",
+            "--quick-set-expire",
+            &fingerprint,
+            "10y",
+        ],
+    )?;
+
+    gpg(home, &["--import", &old_key.to_string_lossy()])?;
+
+    Ok(fingerprint)
+}
+
+/// Return the fingerprint of the key with `user_id` in the home directory `home`.
+fn fingerprint(home: &Path, user_id: &str) -> eyre::Result<String> {
+    let output = gpg(home, &["--list-keys", "--with-colons", user_id])?;
+
+    Ok(String::from_utf8(output)?
+        .lines()
+        .find_map(|line| line.strip_prefix("fpr:::::::::"))
+        .and_then(|line| line.strip_suffix(':'))
+        .ok_or_else(|| eyre!("could not find fingerprint of generated key"))?
+        .to_string())
+}
@@ -1,5 +1,11 @@
 mod client;
 mod gpg;
 
-pub use client::{Key, KeyEncoding, KeyId, PgpClient};
+pub use client::{
+    Fingerprint, HashAlgorithm, Key, KeyAlgorithm, KeyEncoding, KeyExportMode, KeyId, KeyInfo,
+    KeyParameters, PgpClient,
+};
 pub use gpg::GnupgClient;
+
+#[cfg(test)]
+pub use gpg::testing;
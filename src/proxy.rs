@@ -0,0 +1,236 @@
+//! Proxy settings for debctl's own network access.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::Command;
+use std::str::FromStr;
+
+use eyre::{bail, WrapErr};
+use reqwest::Url;
+
+use crate::error::Error;
+
+const APT_CONFIG_CMD: &str = "apt-config";
+
+/// The apt configuration options which set the proxy for each URL scheme.
+const HTTP_PROXY_OPTION: &str = "acquire::http::proxy";
+const HTTPS_PROXY_OPTION: &str = "acquire::https::proxy";
+
+/// The values apt accepts to mean "don't use a proxy".
+const DIRECT_VALUES: [&str; 2] = ["DIRECT", "false"];
+
+/// Whether to use a proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxySetting {
+    /// Connect directly, without a proxy.
+    Direct,
+
+    /// Connect through the proxy at this URL.
+    Url(Url),
+}
+
+impl FromStr for ProxySetting {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if DIRECT_VALUES
+            .iter()
+            .any(|direct| value.eq_ignore_ascii_case(direct))
+        {
+            return Ok(Self::Direct);
+        }
+
+        match Url::parse(value) {
+            Ok(url) if url.has_host() => Ok(Self::Url(url)),
+            _ => Err(Error::InvalidProxy {
+                proxy: value.to_string(),
+            }),
+        }
+    }
+}
+
+/// The proxy settings for a URL scheme.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SchemeProxies {
+    /// The proxy for every host without its own setting.
+    default: Option<ProxySetting>,
+
+    /// The proxies for specific hosts.
+    hosts: HashMap<String, ProxySetting>,
+}
+
+impl SchemeProxies {
+    /// Return the proxy setting for `host`, if there is one.
+    fn get(&self, host: &str) -> Option<&ProxySetting> {
+        self.hosts
+            .get(&host.to_lowercase())
+            .or(self.default.as_ref())
+    }
+
+    /// Whether there are no proxy settings.
+    fn is_empty(&self) -> bool {
+        self.default.is_none() && self.hosts.is_empty()
+    }
+}
+
+/// The proxies to use for debctl's own network access.
+///
+/// If there's no setting for a URL, the `http_proxy` and `https_proxy` environment variables are
+/// used instead, unless there are settings for any other URLs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    http: SchemeProxies,
+    https: SchemeProxies,
+}
+
+impl ProxyConfig {
+    /// Use the same `proxy` setting for every URL.
+    pub fn all(proxy: ProxySetting) -> Self {
+        let proxies = SchemeProxies {
+            default: Some(proxy),
+            hosts: HashMap::new(),
+        };
+
+        Self {
+            http: proxies.clone(),
+            https: proxies,
+        }
+    }
+
+    /// Parse the output of `apt-config dump`.
+    ///
+    /// This reads `Acquire::http::Proxy` and `Acquire::https::Proxy`, along with any per-host
+    /// settings like `Acquire::http::Proxy::example.com`.
+    fn from_apt_config_dump(dump: &str) -> Result<Self, Error> {
+        let mut config = Self::default();
+
+        for line in dump.lines() {
+            let (name, value) = match line
+                .trim()
+                .strip_suffix("\";")
+                .and_then(|line| line.split_once(" \""))
+            {
+                Some((name, value)) => (name.to_lowercase(), value),
+                None => continue,
+            };
+
+            let (proxies, host) = match name.strip_prefix(HTTP_PROXY_OPTION) {
+                Some(host) => (&mut config.http, host),
+                None => match name.strip_prefix(HTTPS_PROXY_OPTION) {
+                    Some(host) => (&mut config.https, host),
+                    None => continue,
+                },
+            };
+
+            match host.strip_prefix("::") {
+                Some(host) => {
+                    proxies.hosts.insert(host.to_string(), value.parse()?);
+                }
+                None if host.is_empty() => proxies.default = Some(value.parse()?),
+                // This is another option, like `Acquire::http::Proxy-Auto-Detect`.
+                None => {}
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Read the proxy settings from apt's configuration.
+    ///
+    /// If apt isn't installed, there are no proxy settings.
+    pub fn from_apt_config() -> eyre::Result<Self> {
+        let output = match Command::new(APT_CONFIG_CMD).arg("dump").output() {
+            Ok(output) => output,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).wrap_err(format!(
+                    "failed reading apt configuration with `{}`",
+                    APT_CONFIG_CMD
+                ))
+            }
+        };
+
+        if !output.status.success() {
+            bail!(
+                "failed reading apt configuration with `{}`: {}",
+                APT_CONFIG_CMD,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(Self::from_apt_config_dump(&String::from_utf8_lossy(
+            &output.stdout,
+        ))?)
+    }
+
+    /// Whether there are no proxy settings.
+    pub fn is_empty(&self) -> bool {
+        self.http.is_empty() && self.https.is_empty()
+    }
+
+    /// Return the proxy setting for `url`, if there is one.
+    ///
+    /// Like apt, HTTPS URLs fall back to the settings for HTTP. Keyserver URLs use the settings
+    /// for the protocol they're built on.
+    pub fn get(&self, url: &Url) -> Option<&ProxySetting> {
+        let host = url.host_str()?;
+
+        match url.scheme() {
+            "http" | "hkp" => self.http.get(host),
+            "https" | "hkps" => self.https.get(host).or_else(|| self.http.get(host)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use xpct::{be_err, equal, expect};
+
+    use super::*;
+
+    const APT_CONFIG_DUMP: &str = r#"Acquire "";
+Acquire::http "";
+Acquire::http::Proxy "http://proxy.example.com:3128";
+Acquire::http::Proxy::deb.internal "DIRECT";
+Acquire::http::Proxy-Auto-Detect "/usr/bin/auto-proxy";
+Acquire::https "";
+Acquire::https::Proxy::secure.internal "false";
+"#;
+
+    #[rstest]
+    #[case("http://deb.debian.org/debian", Some("http://proxy.example.com:3128"))]
+    #[case("https://deb.debian.org/debian", Some("http://proxy.example.com:3128"))]
+    #[case("hkps://keys.openpgp.org", Some("http://proxy.example.com:3128"))]
+    #[case("http://deb.internal/key.asc", None)]
+    #[case("https://secure.internal/key.asc", None)]
+    #[case("file:///etc/apt/keyrings/key.gpg", None)]
+    fn reads_proxies_from_apt_config(
+        #[case] url: &str,
+        #[case] proxy: Option<&str>,
+    ) -> eyre::Result<()> {
+        let config = ProxyConfig::from_apt_config_dump(APT_CONFIG_DUMP)?;
+
+        let setting = config.get(&Url::parse(url)?).map(|setting| match setting {
+            ProxySetting::Url(url) => Some(url.to_string()),
+            ProxySetting::Direct => None,
+        });
+
+        // `Some(None)` means to connect directly.
+        let expected = match proxy {
+            Some(proxy) => Some(Some(Url::parse(proxy)?.to_string())),
+            None if url.starts_with("file:") => None,
+            None => Some(None),
+        };
+
+        expect!(setting).to(equal(expected));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_proxy() {
+        expect!("proxy.example.com:3128".parse::<ProxySetting>()).to(be_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Removing a source along with the files installed for it.
+//!
+//! Besides the source file itself, a source can have credentials in apt's `auth.conf.d` directory
+//! and key files referenced by its `Signed-By` option. Key files are only removed if no other
+//! source uses them.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, WrapErr};
+
+use crate::auth::auth_file_path;
+use crate::error::Error;
+use crate::file::{SourceFile, SourceFileKind, SourceFilePath};
+use crate::sources::installed_entries;
+
+/// The locations a removal deletes files from.
+#[derive(Debug, Clone)]
+pub struct RemoveLocations {
+    /// The path of the APT sources directory.
+    pub sources_dir: PathBuf,
+
+    /// The path of apt's `auth.conf.d` directory.
+    pub auth_dir: PathBuf,
+
+    /// The directory key files are installed to.
+    ///
+    /// Key files outside this directory, like those in `/usr/share/keyrings`, may belong to a
+    /// package, so they're never removed.
+    pub keyring_dir: PathBuf,
+}
+
+/// Why a key file used by the source was left in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeptReason {
+    /// Other sources also use the key file.
+    Shared,
+
+    /// The key file is outside the directory key files are installed to.
+    NotInstalled,
+}
+
+/// The files to delete to remove a source.
+#[derive(Debug)]
+pub struct RemovalPlan {
+    source_files: Vec<PathBuf>,
+    auth_file: Option<PathBuf>,
+    key_files: Vec<PathBuf>,
+    kept_key_files: Vec<(PathBuf, KeptReason)>,
+    removed: bool,
+}
+
+impl RemovalPlan {
+    /// Plan removing the source named `name`.
+    pub fn new(name: &str, locations: &RemoveLocations) -> eyre::Result<Self> {
+        let source_files = [SourceFileKind::Deb822, SourceFileKind::OneLine]
+            .into_iter()
+            .map(|kind| {
+                SourceFile {
+                    path: SourceFilePath::Installed {
+                        dir: locations.sources_dir.clone(),
+                        name: name.to_owned(),
+                    },
+                    kind,
+                }
+                .path()
+                .into_owned()
+            })
+            .collect::<Vec<_>>();
+
+        let existing_files = source_files
+            .iter()
+            .filter(|path| path.is_file())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if existing_files.is_empty() {
+            bail!(Error::SourceFileNotFound {
+                path: source_files[0].clone(),
+            });
+        }
+
+        let entries =
+            installed_entries(&locations.sources_dir).wrap_err("failed reading source entries")?;
+
+        let (removed_entries, other_entries): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|entry| existing_files.contains(&entry.path));
+
+        let mut key_files = Vec::new();
+        let mut kept_key_files = Vec::new();
+
+        for path in removed_entries
+            .iter()
+            .filter_map(|entry| entry.keyring_path())
+        {
+            if key_files.contains(&path) || kept_key_files.iter().any(|(kept, _)| kept == &path) {
+                continue;
+            }
+
+            if other_entries
+                .iter()
+                .any(|entry| entry.keyring_path().as_ref() == Some(&path))
+            {
+                kept_key_files.push((path, KeptReason::Shared));
+            } else if path.parent() != Some(locations.keyring_dir.as_path()) {
+                kept_key_files.push((path, KeptReason::NotInstalled));
+            } else if path.is_file() {
+                key_files.push(path);
+            }
+        }
+
+        let auth_file =
+            Some(auth_file_path(&locations.auth_dir, name)).filter(|path| path.is_file());
+
+        Ok(Self {
+            source_files: existing_files,
+            auth_file,
+            key_files,
+            kept_key_files,
+            removed: false,
+        })
+    }
+
+    /// Delete the files in this plan.
+    ///
+    /// The source files are deleted first, so apt is never left with a source whose key file or
+    /// credentials are missing.
+    pub fn remove(&mut self) -> eyre::Result<()> {
+        for path in &self.source_files {
+            remove_file(path).wrap_err("failed removing source file")?;
+        }
+
+        if let Some(path) = &self.auth_file {
+            remove_file(path).wrap_err("failed removing credentials file")?;
+        }
+
+        for path in &self.key_files {
+            remove_file(path).wrap_err("failed removing key file")?;
+        }
+
+        self.removed = true;
+
+        Ok(())
+    }
+}
+
+/// Delete the file at `path`, if it still exists.
+fn remove_file(path: &Path) -> eyre::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => bail!(Error::PermissionDenied),
+        Err(err) => Err(err.into()),
+    }
+}
+
+impl fmt::Display for RemovalPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.removed {
+            true => "Removed",
+            false => "Would remove",
+        };
+
+        for path in &self.source_files {
+            writeln!(f, "{} source file: {}", verb, path.display())?;
+        }
+
+        if let Some(path) = &self.auth_file {
+            writeln!(f, "{} credentials file: {}", verb, path.display())?;
+        }
+
+        for path in &self.key_files {
+            writeln!(f, "{} signing key: {}", verb, path.display())?;
+        }
+
+        for (path, reason) in &self.kept_key_files {
+            match reason {
+                KeptReason::Shared => writeln!(
+                    f,
+                    "Left signing key in place, because other sources use it: {}",
+                    path.display()
+                )?,
+                KeptReason::NotInstalled => writeln!(
+                    f,
+                    "Left signing key in place, because it may belong to a package: {}",
+                    path.display()
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xpct::{be_err, be_false, be_ok, be_true, equal, expect};
+
+    use super::*;
+
+    #[test]
+    fn removes_source_with_credentials_and_unshared_keys() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+        let root = home.path();
+
+        let locations = RemoveLocations {
+            sources_dir: root.join("sources.list.d"),
+            auth_dir: root.join("auth.conf.d"),
+            keyring_dir: root.join("keyrings"),
+        };
+
+        for dir in [
+            &locations.sources_dir,
+            &locations.auth_dir,
+            &locations.keyring_dir,
+        ] {
+            fs::create_dir_all(dir)?;
+        }
+
+        let own_key = locations.keyring_dir.join("example.gpg");
+        let shared_key = locations.keyring_dir.join("shared.gpg");
+        let package_key = root.join("package.gpg");
+
+        for path in [&own_key, &shared_key, &package_key] {
+            fs::write(path, "key")?;
+        }
+
+        let source_path = locations.sources_dir.join("example.sources");
+        let other_path = locations.sources_dir.join("other.sources");
+        let auth_path = locations.auth_dir.join("example.conf");
+
+        fs::write(
+            &source_path,
+            format!(
+                "Types: deb\nURIs: https://example.com/debian\nSuites: stable\nComponents: main\nSigned-By: {}\n\nTypes: deb\nURIs: https://example.com/debian\nSuites: testing\nComponents: main\nSigned-By: {}\n\nTypes: deb\nURIs: https://example.com/debian\nSuites: unstable\nComponents: main\nSigned-By: {}\n",
+                own_key.display(),
+                shared_key.display(),
+                package_key.display(),
+            ),
+        )?;
+        fs::write(
+            &other_path,
+            format!(
+                "Types: deb\nURIs: https://example.org/debian\nSuites: stable\nComponents: main\nSigned-By: {}\n",
+                shared_key.display(),
+            ),
+        )?;
+        fs::write(
+            &auth_path,
+            "machine example.com/debian login user password secret\n",
+        )?;
+
+        let mut plan = RemovalPlan::new("example", &locations)?;
+
+        expect!(plan.key_files.clone()).to(equal(vec![own_key.clone()]));
+        expect!(plan.kept_key_files.clone()).to(equal(vec![
+            (shared_key.clone(), KeptReason::Shared),
+            (package_key.clone(), KeptReason::NotInstalled),
+        ]));
+
+        plan.remove()?;
+
+        expect!(source_path.exists()).to(be_false());
+        expect!(auth_path.exists()).to(be_false());
+        expect!(own_key.exists()).to(be_false());
+
+        expect!(other_path.exists()).to(be_true());
+        expect!(shared_key.exists()).to(be_true());
+        expect!(package_key.exists()).to(be_true());
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_when_source_does_not_exist() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+
+        let locations = RemoveLocations {
+            sources_dir: home.path().join("sources.list.d"),
+            auth_dir: home.path().join("auth.conf.d"),
+            keyring_dir: home.path().join("keyrings"),
+        };
+
+        expect!(RemovalPlan::new("example", &locations))
+            .to(be_err())
+            .map(|err| err.downcast::<Error>())
+            .to(be_ok())
+            .to(equal(Error::SourceFileNotFound {
+                path: locations.sources_dir.join("example.sources"),
+            }));
+
+        Ok(())
+    }
+}
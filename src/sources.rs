@@ -0,0 +1,533 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, WrapErr};
+
+use crate::error::Error;
+use crate::file::SourceFileKind;
+use crate::key::{KeyRotateAction, KeySource, MINIMAL_KEY_MODE};
+use crate::option::{KnownOptionName, OptionMap, OptionValue};
+use crate::parse::{
+    parse_deb822_file, parse_deb822_option_name, parse_line_file, ConvertedLineEntry, Deb822Entry,
+    ParseLineFileOptions,
+};
+use crate::pgp::{Fingerprint, KeyExportMode};
+
+/// The name of the main APT sources file, which lives next to the sources directory.
+const MAIN_SOURCE_FILE_NAME: &str = "sources.list";
+
+/// A key that a source entry trusts to sign the repository, as listed in its `Signed-By` option.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignedBy {
+    /// The path of a keyring file.
+    Path(PathBuf),
+
+    /// The fingerprint of a key in one of the keyrings.
+    Fingerprint(Fingerprint),
+
+    /// A key inlined into the source entry.
+    Inline,
+}
+
+/// A source entry installed on the system.
+#[derive(Debug, Clone)]
+pub struct InstalledEntry {
+    /// The path of the source file this entry is in.
+    pub path: PathBuf,
+
+    /// The options of the source entry.
+    pub options: OptionMap,
+}
+
+impl InstalledEntry {
+    /// The keys this entry trusts to sign the repository, from its `Signed-By` option.
+    ///
+    /// If this is empty, apt trusts every globally trusted key to sign the repository.
+    pub fn signed_by(&self) -> Vec<SignedBy> {
+        let values = match self.options.get(KnownOptionName::SignedBy) {
+            Some(OptionValue::String(value)) => vec![value.as_str()],
+            Some(OptionValue::List(values)) => values.iter().map(String::as_str).collect(),
+            Some(OptionValue::Multiline(_)) => return vec![SignedBy::Inline],
+            Some(OptionValue::Bool(_)) | None => return Vec::new(),
+        };
+
+        values
+            .into_iter()
+            .map(|value| {
+                if value.starts_with('/') {
+                    SignedBy::Path(PathBuf::from(value))
+                } else {
+                    // A trailing `!` means only this exact key is trusted, not its subkeys.
+                    SignedBy::Fingerprint(Fingerprint::new(value.trim_end_matches('!').to_string()))
+                }
+            })
+            .collect()
+    }
+}
+
+impl InstalledEntry {
+    /// The path of the keyring file this entry trusts, if it references one.
+    pub fn keyring_path(&self) -> Option<PathBuf> {
+        self.signed_by().into_iter().find_map(|key| match key {
+            SignedBy::Path(path) => Some(path),
+            _ => None,
+        })
+    }
+
+    /// Where the signing keys for this entry came from, if debctl recorded it.
+    pub fn key_sources(&self) -> eyre::Result<Vec<KeySource>> {
+        let tokens = match self.options.get(KnownOptionName::KeySource) {
+            Some(OptionValue::String(token)) => vec![token.as_str()],
+            Some(OptionValue::List(tokens)) => tokens.iter().map(String::as_str).collect(),
+            Some(OptionValue::Bool(_) | OptionValue::Multiline(_)) | None => Vec::new(),
+        };
+
+        Ok(tokens
+            .into_iter()
+            .map(KeySource::from_token)
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// How the signing keys for this entry were exported when debctl installed them.
+    pub fn key_export_mode(&self) -> KeyExportMode {
+        match self.options.get(KnownOptionName::KeyMode) {
+            Some(OptionValue::String(mode)) if mode.eq_ignore_ascii_case(MINIMAL_KEY_MODE) => {
+                KeyExportMode::Minimal
+            }
+            _ => KeyExportMode::Full,
+        }
+    }
+
+    /// The fingerprints of the keys this entry trusts, if its `Signed-By` option restricts them.
+    pub fn trusted_fingerprints(&self) -> Vec<Fingerprint> {
+        self.signed_by()
+            .into_iter()
+            .filter_map(|key| match key {
+                SignedBy::Fingerprint(fingerprint) => Some(fingerprint),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The name of the source file this entry is in, without its extension.
+    pub fn file_name(&self) -> Option<&str> {
+        self.path.file_stem().and_then(|stem| stem.to_str())
+    }
+
+    /// Update this entry after the signing keys in its key file were rotated.
+    ///
+    /// This records where the new keys came from, and if the `Signed-By` option restricts which
+    /// keys in the key file are trusted, it trusts the new keys as well. Once the old keys are
+    /// removed, they're no longer recorded or trusted.
+    pub fn rotate_keys(
+        &mut self,
+        action: KeyRotateAction,
+        sources: &[KeySource],
+        fingerprints: &[Fingerprint],
+    ) {
+        let mut tokens = match (action, self.options.get(KnownOptionName::KeySource)) {
+            (KeyRotateAction::AddNew, value) => option_values(value),
+            _ => Vec::new(),
+        };
+
+        match sources
+            .iter()
+            .map(KeySource::to_token)
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(new_tokens) => {
+                for token in new_tokens {
+                    if !tokens.contains(&token) {
+                        tokens.push(token);
+                    }
+                }
+
+                self.options.insert(KnownOptionName::KeySource, tokens);
+            }
+            // If the new keys can't be fetched again, the keyring can no longer be refreshed.
+            None => {
+                self.options.remove(KnownOptionName::KeySource);
+            }
+        }
+
+        let values = match self.options.get(KnownOptionName::SignedBy) {
+            value @ Some(OptionValue::String(_) | OptionValue::List(_)) => option_values(value),
+            _ => return,
+        };
+
+        let (paths, mut trusted): (Vec<_>, Vec<_>) =
+            values.into_iter().partition(|value| value.starts_with('/'));
+
+        // If the entry trusts every key in the key file, there's nothing else to update.
+        if trusted.is_empty() || action == KeyRotateAction::Unchanged {
+            return;
+        }
+
+        // A trailing `!` means only this exact key is trusted, not its subkeys.
+        let is_trusted = |value: &String, fingerprint: &Fingerprint| {
+            Fingerprint::new(value.trim_end_matches('!').to_string()) == *fingerprint
+        };
+
+        if action == KeyRotateAction::RemoveOld {
+            trusted.retain(|value| {
+                fingerprints
+                    .iter()
+                    .any(|fingerprint| is_trusted(value, fingerprint))
+            });
+        }
+
+        for fingerprint in fingerprints {
+            if !trusted.iter().any(|value| is_trusted(value, fingerprint)) {
+                trusted.push(fingerprint.to_string());
+            }
+        }
+
+        self.options.insert(
+            KnownOptionName::SignedBy,
+            paths.into_iter().chain(trusted).collect::<Vec<_>>(),
+        );
+    }
+}
+
+/// The individual values of a whitespace-separated option value, if it's set.
+pub fn option_values(value: Option<&OptionValue>) -> Vec<String> {
+    match value {
+        Some(OptionValue::String(value)) => vec![value.clone()],
+        Some(OptionValue::List(values)) => values.clone(),
+        Some(OptionValue::Bool(_) | OptionValue::Multiline(_)) | None => Vec::new(),
+    }
+}
+
+/// Return the paths of all the source files apt reads, in the order it reads them.
+pub fn source_file_paths(sources_dir: &Path) -> eyre::Result<Vec<(PathBuf, SourceFileKind)>> {
+    let mut paths = Vec::new();
+
+    if let Some(apt_dir) = sources_dir.parent() {
+        let main_path = apt_dir.join(MAIN_SOURCE_FILE_NAME);
+
+        if main_path.is_file() {
+            paths.push((main_path, SourceFileKind::OneLine));
+        }
+    }
+
+    let dir_entries = match fs::read_dir(sources_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(paths),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => bail!(Error::PermissionDenied),
+        Err(err) => return Err(err).wrap_err("failed reading sources directory"),
+    };
+
+    let mut dir_paths = dir_entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err("failed reading sources directory")?;
+
+    dir_paths.sort();
+
+    for path in dir_paths {
+        let kind = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("list") => SourceFileKind::OneLine,
+            Some("sources") => SourceFileKind::Deb822,
+            _ => continue,
+        };
+
+        if path.is_file() {
+            paths.push((path, kind));
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Read the source entries in the source file at `path`.
+///
+/// Disabled entries are included.
+pub fn read_source_file(path: &Path, kind: SourceFileKind) -> eyre::Result<Vec<OptionMap>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => bail!(Error::PermissionDenied),
+        Err(err) => return Err(err).wrap_err("failed opening source file"),
+    };
+
+    let entries = match kind {
+        SourceFileKind::OneLine => parse_line_file(
+            file,
+            &ParseLineFileOptions {
+                skip_comments: true,
+                skip_disabled: false,
+            },
+        )?
+        .into_iter()
+        .filter_map(|entry| match entry {
+            ConvertedLineEntry::Entry(options) => Some(options),
+            ConvertedLineEntry::Comment(_) => None,
+        })
+        .collect(),
+        SourceFileKind::Deb822 => parse_deb822_file(file, true)?
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Deb822Entry::Entry(options) => Some(options),
+                Deb822Entry::Comment(_) => None,
+            })
+            .collect(),
+    };
+
+    Ok(entries)
+}
+
+/// Return all the source entries installed in `sources_dir` and the main sources file.
+pub fn installed_entries(sources_dir: &Path) -> eyre::Result<Vec<InstalledEntry>> {
+    let mut entries = Vec::new();
+
+    for (path, kind) in source_file_paths(sources_dir)? {
+        let options_list = read_source_file(&path, kind)
+            .wrap_err_with(|| format!("failed reading source file: {}", path.display()))?;
+
+        entries.extend(options_list.into_iter().map(|options| InstalledEntry {
+            path: path.clone(),
+            options,
+        }));
+    }
+
+    Ok(entries)
+}
+
+/// Return whether this line of a deb822-style source file starts a field.
+fn is_field_line(line: &str) -> bool {
+    !line.trim().is_empty() && !line.starts_with('#') && !line.starts_with(char::is_whitespace)
+}
+
+/// Apply the changes between the `original` and `updated` options to the `lines` of a stanza.
+///
+/// Fields which didn't change are left exactly as they were, along with any comments. Changed
+/// fields are rewritten where they are, and new fields are added to the end of the stanza.
+fn update_stanza(
+    lines: &[&str],
+    original: &OptionMap,
+    updated: &OptionMap,
+    output: &mut Vec<u8>,
+) -> io::Result<()> {
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        index += 1;
+
+        let field_name = match line.split_once(':') {
+            Some((name, _)) if is_field_line(line) => name.trim(),
+            _ => {
+                writeln!(output, "{}", line)?;
+                continue;
+            }
+        };
+
+        // The field includes any continuation lines after it.
+        let start = index - 1;
+
+        while index < lines.len() && lines[index].starts_with(char::is_whitespace) {
+            index += 1;
+        }
+
+        let name = parse_deb822_option_name(field_name);
+
+        match updated.get(name.clone()) {
+            Some(value) if original.get(name) != Some(value) => {
+                value.write_deb822_field(field_name, &mut *output)?;
+            }
+            Some(_) => {
+                for line in &lines[start..index] {
+                    writeln!(output, "{}", line)?;
+                }
+            }
+            // The field was removed.
+            None => {}
+        }
+    }
+
+    for (name, value) in updated.options() {
+        if !original.contains(name.clone()) {
+            value.write_deb822_field(name.to_deb822(), &mut *output)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply the changes between the `original` and `updated` entries to the `contents` of a
+/// deb822-style source file.
+///
+/// This only rewrites the fields that changed, so the rest of the file, including its comments
+/// and formatting, stays the way the user wrote it. The `original` entries must be the ones parsed
+/// from `contents`.
+pub fn update_deb822_contents(
+    contents: &str,
+    original: &[Deb822Entry],
+    updated: &[Deb822Entry],
+) -> io::Result<Vec<u8>> {
+    let mut stanzas = original
+        .iter()
+        .zip(updated)
+        .filter_map(|entries| match entries {
+            (Deb822Entry::Entry(original), Deb822Entry::Entry(updated)) => {
+                Some((original, updated))
+            }
+            _ => None,
+        });
+
+    let lines = contents.lines().collect::<Vec<_>>();
+    let mut output = Vec::new();
+    let mut index = 0;
+
+    while index < lines.len() {
+        // Stanzas and comments are separated by blank lines.
+        let end = lines[index..]
+            .iter()
+            .position(|line| line.trim().is_empty())
+            .map_or(lines.len(), |offset| index + offset);
+
+        let group = &lines[index..end];
+
+        let stanza = match group.iter().any(|line| is_field_line(line)) {
+            true => stanzas.next(),
+            false => None,
+        };
+
+        match stanza {
+            Some((original, updated)) => update_stanza(group, original, updated, &mut output)?,
+            None => {
+                for line in group {
+                    writeln!(output, "{}", line)?;
+                }
+            }
+        }
+
+        if end < lines.len() {
+            writeln!(output, "{}", lines[end])?;
+        }
+
+        index = end + 1;
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use xpct::{equal, expect};
+
+    use super::*;
+
+    const OLD_FINGERPRINT: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+    const NEW_FINGERPRINT: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+
+    /// An entry trusting the old key in the given key file.
+    fn entry(key_path: &str) -> InstalledEntry {
+        let mut options = OptionMap::new();
+
+        options.insert(
+            KnownOptionName::SignedBy,
+            vec![key_path.to_string(), OLD_FINGERPRINT.to_string()],
+        );
+        options.insert(KnownOptionName::KeySource, "https://example.com/old.gpg");
+
+        InstalledEntry {
+            path: PathBuf::from("/etc/apt/sources.list.d/example.sources"),
+            options,
+        }
+    }
+
+    fn new_source() -> KeySource {
+        KeySource::from_token("https://example.com/new.gpg").unwrap()
+    }
+
+    #[test]
+    fn adding_new_key_trusts_both_keys() {
+        let mut entry = entry("/etc/apt/keyrings/example.gpg");
+
+        entry.rotate_keys(
+            KeyRotateAction::AddNew,
+            &[new_source()],
+            &[Fingerprint::new(NEW_FINGERPRINT.to_string())],
+        );
+
+        expect!(entry.options.get(KnownOptionName::SignedBy).cloned()).to(equal(Some(
+            OptionValue::List(vec![
+                String::from("/etc/apt/keyrings/example.gpg"),
+                String::from(OLD_FINGERPRINT),
+                String::from(NEW_FINGERPRINT),
+            ]),
+        )));
+
+        expect!(entry.options.get(KnownOptionName::KeySource).cloned()).to(equal(Some(
+            OptionValue::List(vec![
+                String::from("https://example.com/old.gpg"),
+                String::from("https://example.com/new.gpg"),
+            ]),
+        )));
+    }
+
+    #[test]
+    fn removing_old_key_only_trusts_new_key() {
+        let mut entry = entry("/etc/apt/keyrings/example.gpg");
+
+        entry.rotate_keys(
+            KeyRotateAction::RemoveOld,
+            &[new_source()],
+            &[Fingerprint::new(NEW_FINGERPRINT.to_string())],
+        );
+
+        expect!(entry.options.get(KnownOptionName::SignedBy).cloned()).to(equal(Some(
+            OptionValue::List(vec![
+                String::from("/etc/apt/keyrings/example.gpg"),
+                String::from(NEW_FINGERPRINT),
+            ]),
+        )));
+
+        expect!(entry.options.get(KnownOptionName::KeySource).cloned()).to(equal(Some(
+            OptionValue::String(String::from("https://example.com/new.gpg")),
+        )));
+    }
+
+    #[test]
+    fn rotating_to_key_from_stdin_stops_recording_key_sources() {
+        let mut entry = entry("/etc/apt/keyrings/example.gpg");
+
+        entry.rotate_keys(
+            KeyRotateAction::AddNew,
+            &[KeySource::Stdin { key: Vec::new() }],
+            &[Fingerprint::new(NEW_FINGERPRINT.to_string())],
+        );
+
+        expect!(entry.options.get(KnownOptionName::KeySource).cloned()).to(equal(None));
+    }
+
+    #[test]
+    fn updating_contents_only_rewrites_changed_fields() -> eyre::Result<()> {
+        let contents = "#Example repository\n\ntypes: deb\nURIs: https://example.com\n# Old key\nSigned-By: /etc/apt/keyrings/example.gpg\n  AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\nX-Debctl-Key-Source: https://example.com/old.gpg\nSuites: stable\n\n\nTypes: deb-src\nURIs: https://example.com\nSuites: stable\n";
+
+        let original = parse_deb822_file(contents.as_bytes(), false)?;
+        let mut updated = original.clone();
+
+        if let Some(options) = updated.iter_mut().find_map(|entry| match entry {
+            Deb822Entry::Entry(options) => Some(options),
+            Deb822Entry::Comment(_) => None,
+        }) {
+            options.insert(
+                KnownOptionName::SignedBy,
+                vec!["/etc/apt/keyrings/example.gpg", NEW_FINGERPRINT],
+            );
+            options.remove(KnownOptionName::KeySource);
+            options.insert(KnownOptionName::Components, vec!["main"]);
+        }
+
+        expect!(String::from_utf8(update_deb822_contents(
+            contents, &original, &updated
+        )?)?)
+        .to(equal(format!(
+            "#Example repository\n\ntypes: deb\nURIs: https://example.com\n# Old key\nSigned-By: /etc/apt/keyrings/example.gpg {NEW_FINGERPRINT}\nSuites: stable\nComponents: main\n\n\nTypes: deb-src\nURIs: https://example.com\nSuites: stable\n"
+        )));
+
+        Ok(())
+    }
+}
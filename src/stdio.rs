@@ -1,9 +1,15 @@
 use std::io::{self, Read};
+use std::path::Path;
 use std::process::Child;
 use std::thread::JoinHandle;
 
 use eyre::{bail, WrapErr};
 
+/// Return whether this path is "-", meaning to read from stdin or write to stdout.
+pub fn path_is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
 /// Write to a command's stdin and close the stream.
 pub fn write_stdin(process: &mut Child, mut src: impl Read) -> eyre::Result<()> {
     let mut stdin = process.stdin.take().unwrap();
@@ -1,9 +1,11 @@
 use std::fmt::{self, Display};
+use std::path::Path;
 use std::str::FromStr;
 
 use clap::ValueEnum;
 
 use crate::error::Error;
+use crate::pgp::KeyEncoding;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum SourceType {
@@ -38,3 +40,40 @@ impl FromStr for SourceType {
         }
     }
 }
+
+/// The file format of an installed signing key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyFormat {
+    /// A binary keyring with a `.gpg` extension
+    Binary,
+
+    /// An ASCII-armored keyring with an `.asc` extension
+    Armored,
+}
+
+impl KeyFormat {
+    /// The encoding of keys in this format.
+    pub fn encoding(self) -> KeyEncoding {
+        match self {
+            Self::Binary => KeyEncoding::Binary,
+            Self::Armored => KeyEncoding::Armored,
+        }
+    }
+
+    /// The file extension apt expects for keys in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Binary => "gpg",
+            Self::Armored => "asc",
+        }
+    }
+
+    /// Infer the format from the file extension of `path`, if it has a known one.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gpg" => Some(Self::Binary),
+            "asc" => Some(Self::Armored),
+            _ => None,
+        }
+    }
+}